@@ -0,0 +1,15 @@
+#![no_main]
+
+use archivindex_wxj::lines::SnapshotLine;
+use libfuzzer_sys::fuzz_target;
+
+// Parsing arbitrary input should never panic, and any line we accept should survive a round trip through our
+// printed representation.
+fuzz_target!(|line: &str| {
+    if let Ok(parsed) = SnapshotLine::parse(line) {
+        let printed = parsed.to_string();
+        let reparsed = SnapshotLine::parse(&printed).expect("printed snapshot line should parse");
+
+        assert_eq!(parsed, reparsed);
+    }
+});
@@ -1,8 +1,6 @@
-use archivindex_wbm::{
-    cdx::{item::ItemList, mime_type::MimeType},
-    surt::Surt,
-};
+use archivindex_wbm::digest::Digest;
 use archivindex_wxj::lines::{Snapshot, SnapshotLine};
+use archivindex_wxj::url_paths::{UrlPathIndex, read_url_paths};
 use birdsite::model::wxj::{data, flat};
 use cli_helpers::prelude::*;
 use std::fs::File;
@@ -11,6 +9,8 @@ use std::path::{Path, PathBuf};
 
 mod wxj;
 
+const URL_PATH_INDEX_INTERVAL: usize = 1024;
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let opts: Opts = Opts::parse();
@@ -33,12 +33,12 @@ async fn main() -> Result<(), Error> {
                     && (include_timestamped || snapshot_line.timestamp.is_none())
                 {
                     let url = if flat {
-                        Some(wxj::flat_canonical_url(
+                        Some(archivindex_wxj::canonical::flat_canonical_url(
                             &serde_json::from_str::<Snapshot<flat::TweetSnapshot>>(&line)?.content,
                             false,
                         ))
                     } else {
-                        wxj::data_canonical_url(
+                        archivindex_wxj::canonical::data_canonical_url(
                             &serde_json::from_str::<Snapshot<data::TweetSnapshot>>(&line)?.content,
                             false,
                         )
@@ -63,15 +63,19 @@ async fn main() -> Result<(), Error> {
             invalid_digests,
             output,
             compression_level,
+            bounded_memory,
         } => {
-            let url_paths = wxj::read_url_paths(urls)?;
-            log::info!("{} URL path entries", url_paths.len());
+            let mut digest_metadata = if bounded_memory {
+                let mut url_paths = UrlPathIndex::open(urls, URL_PATH_INDEX_INTERVAL)?;
+                log::info!("{} URL path entries", url_paths.len());
 
-            let mut digest_metadata = wxj::read_cdx(cdx, &url_paths)?;
-            log::info!("{} digest metadata entries", digest_metadata.len());
+                wxj::read_digest_metadata(cdx, invalid_digests, &mut url_paths)?
+            } else {
+                let mut url_paths = read_url_paths(urls)?;
+                log::info!("{} URL path entries", url_paths.len());
 
-            wxj::read_invalid_digests(invalid_digests, &url_paths, &mut digest_metadata)?;
-            log::info!("{} digest metadata entries", digest_metadata.len());
+                wxj::read_digest_metadata(cdx, invalid_digests, &mut url_paths)?
+            };
 
             let inferred_urls = digest_metadata
                 .values()
@@ -95,13 +99,16 @@ async fn main() -> Result<(), Error> {
                     Some(metadata) => {
                         if let Some((previous, replacement)) = snapshot_line
                             .expected_digest
+                            .as_ref()
                             .zip(metadata.expected_digest)
-                            .filter(|(previous, replacement)| previous != replacement)
+                            .filter(|(previous, replacement)| {
+                                previous.valid() != Some(*replacement)
+                            })
                         {
                             log::warn!("Replacing expected digest: {previous}, {replacement}");
                         }
 
-                        snapshot_line.expected_digest = metadata.expected_digest;
+                        snapshot_line.expected_digest = metadata.expected_digest.map(Digest::from);
 
                         if let Some(previous) = snapshot_line
                             .timestamp
@@ -137,11 +144,11 @@ async fn main() -> Result<(), Error> {
             let validation = if input.as_os_str().to_string_lossy().ends_with("zst") {
                 let lines = BufReader::new(zstd::Decoder::new(File::open(input)?)?).lines();
 
-                archivindex_wxj::lines::SnapshotLine::validate_lines(lines)
+                archivindex_wxj::lines::SnapshotLine::validate_lines_with_content(lines)
             } else {
                 let lines = BufReader::new(File::open(input)?).lines();
 
-                archivindex_wxj::lines::SnapshotLine::validate_lines(lines)
+                archivindex_wxj::lines::SnapshotLine::validate_lines_with_content(lines)
             }?;
 
             println!("Successful: {}", validation.valid_count);
@@ -151,6 +158,7 @@ async fn main() -> Result<(), Error> {
                 validation.unexpected_digests.len()
             );
             println!("Out of order lines: {}", validation.out_of_order.len());
+            println!("Invalid content: {}", validation.invalid_content.len());
         }
         Command::CdxList { base } => {
             for path in wxj::cdx_files(base).unwrap() {
@@ -163,26 +171,23 @@ async fn main() -> Result<(), Error> {
             let mut failure_count = 0;
 
             for path in cdx_paths {
-                let contents = std::fs::read_to_string(&path)?;
-
-                match serde_json::from_str::<ItemList>(&contents) {
+                match archivindex_wbm::cdx::read_item_list(&path) {
                     Ok(items) => {
-                        for item in items.values {
-                            if item.mime_type == MimeType::ApplicationJson {
-                                let converted_surt = Surt::from_url(&item.original)?;
-
-                                if converted_surt == item.key {
-                                    success_count += 1;
-                                } else {
-                                    log::error!(
-                                        "Invalid conversion in {path:?}:\nConverted: {converted_surt}\nOriginal:  {}",
-                                        item.key
-                                    );
-
-                                    failure_count += 1;
-                                }
-                            }
+                        let (file_success_count, mismatches) = items.check_json_surts()?;
+                        let file_failure_count = mismatches.len();
+
+                        for (original, converted_surt) in mismatches {
+                            log::error!(
+                                "Invalid conversion in {path:?}:\nConverted: {converted_surt}\nOriginal:  {original}"
+                            );
                         }
+
+                        log::info!(
+                            "At {path:?}: good: {file_success_count}; bad: {file_failure_count}"
+                        );
+
+                        success_count += file_success_count;
+                        failure_count += file_failure_count;
                     }
                     Err(error) => {
                         log::error!("At {path:?}: {error:?}");
@@ -211,6 +216,8 @@ pub enum Error {
     Surt(#[from] archivindex_wbm::surt::Error),
     #[error("WXJ lines error")]
     WxjLines(#[from] archivindex_wxj::lines::Error),
+    #[error("URL path error")]
+    UrlPaths(#[from] archivindex_wxj::url_paths::Error),
     #[error("WXJ hacking error")]
     Wxj(#[from] wxj::Error),
 }
@@ -247,6 +254,8 @@ enum Command {
         output: PathBuf,
         #[clap(long, default_value = "14")]
         compression_level: i32,
+        #[clap(long)]
+        bounded_memory: bool,
     },
     ValidatedWxjLines {
         #[clap(long)]
@@ -1,4 +1,5 @@
 use archivindex_wbm::{cdx::item::ItemList, digest::Sha1Digest, timestamp::Timestamp};
+use archivindex_wxj::url_paths::UrlPathLookup;
 use cli_helpers::prelude::log;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
@@ -19,6 +20,8 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error("Digest error")]
     Digest(#[from] archivindex_wbm::digest::Error),
+    #[error("URL path error")]
+    UrlPaths(#[from] archivindex_wxj::url_paths::Error),
     #[error("Invalid input line")]
     InvalidLine(String),
 }
@@ -61,38 +64,6 @@ impl Metadata {
     }
 }
 
-#[derive(serde::Deserialize)]
-struct DigestUrl {
-    digest: Sha1Digest,
-    url: Option<String>,
-}
-
-pub fn read_url_paths<P: AsRef<Path>>(
-    input: P,
-) -> Result<BTreeMap<Sha1Digest, Option<String>>, Error> {
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .from_path(&input)?;
-
-    reader
-        .deserialize::<DigestUrl>()
-        .map(|row| {
-            row.and_then(|DigestUrl { digest, url }| {
-                let url = url
-                    .map(|url| {
-                        url.strip_prefix("https://twitter.com")
-                            .map(|path| path.to_string())
-                            .ok_or_else(|| <csv::Error as serde::ser::Error>::custom("Twitter URL"))
-                    })
-                    .map_or(Ok(None), |value| value.map(Some))?;
-
-                Ok((digest, url))
-            })
-            .map_err(Error::from)
-        })
-        .collect()
-}
-
 pub fn cdx_files<P: AsRef<Path>>(base: P) -> Result<Vec<PathBuf>, Error> {
     let walker = globwalk::GlobWalkerBuilder::new(base, "**/data/*.json")
         .sort_by(|a, b| {
@@ -114,9 +85,23 @@ pub fn cdx_files<P: AsRef<Path>>(base: P) -> Result<Vec<PathBuf>, Error> {
         .collect()
 }
 
-pub fn read_cdx<P: AsRef<Path>>(
+pub fn read_digest_metadata<P: AsRef<Path>, Q: AsRef<Path>, L: UrlPathLookup>(
+    cdx: P,
+    invalid_digests: Q,
+    url_paths: &mut L,
+) -> Result<BTreeMap<Sha1Digest, Metadata>, Error> {
+    let mut digest_metadata = read_cdx(cdx, url_paths)?;
+    log::info!("{} digest metadata entries", digest_metadata.len());
+
+    read_invalid_digests(invalid_digests, url_paths, &mut digest_metadata)?;
+    log::info!("{} digest metadata entries", digest_metadata.len());
+
+    Ok(digest_metadata)
+}
+
+pub fn read_cdx<P: AsRef<Path>, L: UrlPathLookup>(
     base: P,
-    url_paths: &BTreeMap<Sha1Digest, Option<String>>,
+    url_paths: &mut L,
 ) -> Result<BTreeMap<Sha1Digest, Metadata>, Error> {
     let mut digest_metadata_map = BTreeMap::new();
 
@@ -125,10 +110,8 @@ pub fn read_cdx<P: AsRef<Path>>(
         let items = serde_json::from_str::<ItemList>(&content)?;
 
         for item in items.values {
-            if let Some((digest, inferred_url_path)) = item
-                .digest
-                .valid()
-                .and_then(|digest| url_paths.get(&digest).map(|url_path| (digest, url_path)))
+            if let Some(digest) = item.digest.valid()
+                && let Some(inferred_url_path) = url_paths.lookup(digest)?
             {
                 let digest_metadata =
                     Metadata::new(item.timestamp, &item.original, inferred_url_path.as_deref());
@@ -160,9 +143,9 @@ struct InvalidDigest {
     digest: Sha1Digest,
 }
 
-pub fn read_invalid_digests<P: AsRef<Path>>(
+pub fn read_invalid_digests<P: AsRef<Path>, L: UrlPathLookup>(
     input: P,
-    url_paths: &BTreeMap<Sha1Digest, Option<String>>,
+    url_paths: &mut L,
     digest_metadata_map: &mut BTreeMap<Sha1Digest, Metadata>,
 ) -> Result<(), Error> {
     let mut reader = csv::ReaderBuilder::new()
@@ -172,7 +155,7 @@ pub fn read_invalid_digests<P: AsRef<Path>>(
     for record in reader.deserialize::<InvalidDigest>() {
         let invalid_digest = record?;
 
-        if let Some(inferred_url_path) = url_paths.get(&invalid_digest.digest) {
+        if let Some(inferred_url_path) = url_paths.lookup(invalid_digest.digest)? {
             let digest_metadata = Metadata::new(
                 invalid_digest.timestamp,
                 &invalid_digest.url,
@@ -195,29 +178,3 @@ pub fn read_invalid_digests<P: AsRef<Path>>(
 
     Ok(())
 }
-
-pub fn data_canonical_url(
-    snapshot: &birdsite::model::wxj::data::TweetSnapshot,
-    use_x: bool,
-) -> Option<String> {
-    snapshot.lookup_user(snapshot.data.author_id).map(|user| {
-        format!(
-            "https://{}.com/{}/status/{}",
-            if use_x { "x" } else { "twitter" },
-            user.username,
-            snapshot.data.id
-        )
-    })
-}
-
-pub fn flat_canonical_url(
-    snapshot: &birdsite::model::wxj::flat::TweetSnapshot,
-    use_x: bool,
-) -> String {
-    format!(
-        "https://{}.com/{}/status/{}",
-        if use_x { "x" } else { "twitter" },
-        snapshot.user.screen_name,
-        snapshot.id
-    )
-}
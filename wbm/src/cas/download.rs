@@ -0,0 +1,269 @@
+//! Async downloading of Wayback Machine captures into a content-addressed directory.
+//!
+//! Files are named by the Base32 SHA-1 digest of their contents, with an optional compression extension, so that the
+//! directory can be read by `cas::import`.
+
+use crate::cas::import::CompressionType;
+use crate::cdx::item::Item;
+use crate::digest::{Sha1Computer, Sha1Digest};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempPath;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+
+const WAYBACK_ORIGIN: &str = "https://web.archive.org";
+
+/// Default maximum number of concurrent requests.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("HTTP error")]
+    Http(#[from] reqwest::Error),
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[error("Task error")]
+    Task(#[from] tokio::task::JoinError),
+    #[error("Invalid digest")]
+    InvalidDigest {
+        expected: Sha1Digest,
+        found: Sha1Digest,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Downloader {
+    client: reqwest::Client,
+    base: PathBuf,
+    origin: String,
+    compression_type: Option<CompressionType>,
+    permits: Arc<Semaphore>,
+}
+
+impl Downloader {
+    /// Create a downloader that stores gzip-compressed captures in the given directory.
+    pub fn new<P: AsRef<Path>>(client: reqwest::Client, base: P) -> Self {
+        Self {
+            client,
+            base: base.as_ref().to_path_buf(),
+            origin: WAYBACK_ORIGIN.to_string(),
+            compression_type: Some(CompressionType::Gzip),
+            permits: Arc::new(Semaphore::new(DEFAULT_CONCURRENCY)),
+        }
+    }
+
+    /// Use a different origin for Wayback Machine URLs (for example a test server).
+    pub fn origin(mut self, origin: &str) -> Self {
+        self.origin = origin.to_string();
+        self
+    }
+
+    /// Compress stored captures with the given compression type (or not at all).
+    pub fn compression_type(mut self, compression_type: Option<CompressionType>) -> Self {
+        self.compression_type = compression_type;
+        self
+    }
+
+    /// Limit the number of requests that may be in progress at once across all clones of this downloader.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(concurrency));
+        self
+    }
+
+    /// The URL for the raw (`id_`) form of the capture.
+    pub fn capture_url(&self, item: &Item<'_>) -> String {
+        let url = item.entry_info().url_parts.to_wb_url(true, true);
+
+        match url.strip_prefix(WAYBACK_ORIGIN) {
+            Some(path) if self.origin != WAYBACK_ORIGIN => format!("{}{path}", self.origin),
+            _ => url,
+        }
+    }
+
+    /// The path a capture with the given digest is stored at.
+    pub fn path(&self, digest: Sha1Digest) -> PathBuf {
+        match self.compression_type {
+            Some(compression_type) => self
+                .base
+                .join(format!("{digest}.{}", compression_type.extension())),
+            None => self.base.join(digest.to_string()),
+        }
+    }
+
+    /// Download a capture, verify it against the item's digest (if valid), and store it.
+    ///
+    /// The body is streamed to a temporary file in the base directory, which is then compressed (if necessary) and
+    /// renamed on a blocking thread, so incomplete or invalid captures are never stored. If a file for the digest
+    /// already exists it is left unchanged.
+    pub async fn fetch(&self, item: &Item<'_>) -> Result<Sha1Digest, Error> {
+        // The semaphore is never closed.
+        let _permit = self.permits.acquire().await.unwrap();
+
+        let mut response = self
+            .client
+            .get(self.capture_url(item))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let base = self.base.clone();
+        let (file, temp_path) =
+            tokio::task::spawn_blocking(move || tempfile::NamedTempFile::new_in(base))
+                .await??
+                .into_parts();
+        let mut file = tokio::fs::File::from_std(file);
+
+        let computer = Sha1Computer::default();
+
+        while let Some(chunk) = response.chunk().await? {
+            computer.update(&mut chunk.as_ref())?;
+            file.write_all(&chunk).await?;
+        }
+
+        file.flush().await?;
+
+        let digest = computer.finish();
+
+        if let Some(expected) = item.digest.valid()
+            && !digest.ct_eq(&expected)
+        {
+            tokio::task::spawn_blocking(move || temp_path.close()).await??;
+
+            return Err(Error::InvalidDigest {
+                expected,
+                found: digest,
+            });
+        }
+
+        let base = self.base.clone();
+        let path = self.path(digest);
+        let compression_type = self.compression_type;
+
+        tokio::task::spawn_blocking(move || store(temp_path, &path, &base, compression_type))
+            .await??;
+
+        Ok(digest)
+    }
+}
+
+/// Move a downloaded capture into place, compressing it if necessary.
+fn store(
+    temp_path: TempPath,
+    path: &Path,
+    base: &Path,
+    compression_type: Option<CompressionType>,
+) -> Result<(), std::io::Error> {
+    if path.exists() {
+        temp_path.close()
+    } else {
+        match compression_type {
+            Some(compression_type) => {
+                let mut file = tempfile::NamedTempFile::new_in(base)?;
+                let mut input = std::io::BufReader::new(std::fs::File::open(&temp_path)?);
+
+                compression_type.encode(&mut input, file.as_file_mut())?;
+                file.persist(path).map_err(|error| error.error)?;
+
+                temp_path.close()
+            }
+            None => temp_path.persist(path).map_err(|error| error.error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cas::import::{File, Importer};
+    use tokio::io::AsyncReadExt;
+
+    const CONTENT: &[u8] = b"{\"data\":{\"id\":\"20\",\"text\":\"just setting up my twttr\"}}";
+
+    /// Serve the given content for every request.
+    async fn serve(content: &'static [u8]) -> std::io::Result<String> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let origin = format!("http://{}", listener.local_addr()?);
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(count) => request.extend_from_slice(&buffer[..count]),
+                    }
+                }
+
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    content.len()
+                );
+
+                let _ = stream.write_all(header.as_bytes()).await;
+                let _ = stream.write_all(content).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        Ok(origin)
+    }
+
+    fn item(digest: &str) -> Item<'static> {
+        Item {
+            key: "com,twitter)/jack/status/20".parse().unwrap(),
+            timestamp: "20060321205050".parse().unwrap(),
+            original: "https://twitter.com/jack/status/20".into(),
+            mime_type: "application/json".parse().unwrap(),
+            status_code: "200".parse().unwrap(),
+            digest: digest.parse().unwrap(),
+            length: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_capture() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let origin = serve(CONTENT).await?;
+        let expected = Sha1Computer::compute_digest(&mut &CONTENT[..])?;
+
+        let downloader = Downloader::new(reqwest::Client::new(), dir.path())
+            .origin(&origin)
+            .concurrency(1);
+        let item = item(&expected.to_string());
+
+        assert_eq!(
+            downloader.capture_url(&item),
+            format!("{origin}/web/20060321205050id_/https://twitter.com/jack/status/20")
+        );
+
+        let digest = downloader.fetch(&item).await?;
+
+        assert_eq!(digest, expected);
+        assert!(downloader.path(digest).ends_with(format!("{digest}.gz")));
+
+        let validated = Importer::new(dir.path())
+            .validating()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(validated.len(), 1);
+        assert!(matches!(
+            validated[0],
+            File::Valid { digest: found, compression_type: Some(CompressionType::Gzip), .. } if found == expected
+        ));
+
+        let wrong = item(&Sha1Digest::MIN.to_string());
+
+        assert!(matches!(
+            downloader.fetch(&wrong).await,
+            Err(Error::InvalidDigest { found, .. }) if found == expected
+        ));
+
+        // The temporary file for the invalid capture has been removed.
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+
+        Ok(())
+    }
+}
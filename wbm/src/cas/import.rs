@@ -3,6 +3,8 @@ use sha1::{Digest, Sha1};
 use std::fs::ReadDir;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -17,10 +19,96 @@ pub enum Error {
     },
 }
 
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: [u8; 6] = [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+const BZIP2_MAGIC: [u8; 3] = [0x42, 0x5a, 0x68];
+const MAX_MAGIC_LEN: usize = XZ_MAGIC.len();
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum CompressionType {
     Zstd,
     Gzip,
+    Xz,
+    Bzip2,
+}
+
+impl CompressionType {
+    /// Detect the compression type from the first bytes of a file.
+    pub fn from_magic(start: &[u8]) -> Option<Self> {
+        if start.starts_with(&ZSTD_MAGIC) {
+            Some(Self::Zstd)
+        } else if start.starts_with(&GZIP_MAGIC) {
+            Some(Self::Gzip)
+        } else if start.starts_with(&XZ_MAGIC) {
+            Some(Self::Xz)
+        } else if start.starts_with(&BZIP2_MAGIC) {
+            Some(Self::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "zst" => Some(Self::Zstd),
+            "gz" => Some(Self::Gzip),
+            "xz" => Some(Self::Xz),
+            "bz2" => Some(Self::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// The file name extension used for this compression type.
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::Zstd => "zst",
+            Self::Gzip => "gz",
+            Self::Xz => "xz",
+            Self::Bzip2 => "bz2",
+        }
+    }
+
+    /// Wrap a reader in a decoder for this compression type.
+    pub fn decoder<'a, R: Read + 'a>(
+        self,
+        reader: R,
+    ) -> Result<Box<dyn Read + 'a>, std::io::Error> {
+        Ok(match self {
+            Self::Zstd => Box::new(zstd::Decoder::new(reader)?),
+            Self::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Self::Xz => Box::new(xz2::read::XzDecoder::new(reader)),
+            Self::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        })
+    }
+
+    /// Compress the input with default settings, finishing the compressed stream and returning the writer.
+    pub fn encode<R: Read, W: Write>(self, input: &mut R, writer: W) -> Result<W, std::io::Error> {
+        match self {
+            Self::Zstd => {
+                let mut encoder = zstd::Encoder::new(writer, 0)?;
+                std::io::copy(input, &mut encoder)?;
+                encoder.finish()
+            }
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+                std::io::copy(input, &mut encoder)?;
+                encoder.finish()
+            }
+            Self::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(writer, 6);
+                std::io::copy(input, &mut encoder)?;
+                encoder.finish()
+            }
+            Self::Bzip2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(writer, bzip2::Compression::default());
+                std::io::copy(input, &mut encoder)?;
+                encoder.finish()
+            }
+        }
+    }
 }
 
 pub enum File {
@@ -49,10 +137,9 @@ impl File {
 
                     let compression_type = match parts.len() {
                         1 => None,
-                        2 => match parts[1].to_ascii_lowercase().as_str() {
-                            "zst" => Some(CompressionType::Zstd),
-                            "gz" => Some(CompressionType::Gzip),
-                            _ => {
+                        2 => match CompressionType::from_extension(parts[1]) {
+                            Some(compression_type) => Some(compression_type),
+                            None => {
                                 return Self::skipped(path);
                             }
                         },
@@ -73,6 +160,27 @@ impl File {
             )
     }
 
+    /// Create a file as in `new`, but detect the compression type from the file's first bytes.
+    ///
+    /// The detected compression type takes precedence over the file name extension.
+    pub fn new_sniffed<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
+        match Self::new(path) {
+            Self::Valid { path, digest, .. } => {
+                let mut start = Vec::with_capacity(MAX_MAGIC_LEN);
+                std::fs::File::open(&path)?
+                    .take(MAX_MAGIC_LEN as u64)
+                    .read_to_end(&mut start)?;
+
+                Ok(Self::Valid {
+                    compression_type: CompressionType::from_magic(&start),
+                    path,
+                    digest,
+                })
+            }
+            skipped => Ok(skipped),
+        }
+    }
+
     pub fn path(&self) -> &Path {
         match self {
             Self::Valid { path, .. } => path,
@@ -112,6 +220,20 @@ impl Importer {
         ValidatingImporter {
             underlying: self,
             hasher: Sha1::default(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Validate files on the given number of threads, each with its own hasher.
+    ///
+    /// Results are returned as they are completed, so their order is not deterministic.
+    pub fn par_validating(self, threads: usize) -> ParallelValidatingImporter {
+        ParallelValidatingImporter {
+            state: ParallelState::Pending {
+                underlying: self,
+                threads,
+                retry_policy: RetryPolicy::default(),
+            },
         }
     }
 }
@@ -151,9 +273,69 @@ impl Iterator for Importer {
     }
 }
 
+/// Determines how reads that fail with transient errors are retried.
+///
+/// Only `Interrupted`, `TimedOut`, and `WouldBlock` errors are considered transient, and other errors are returned
+/// immediately.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// The delay before the first retry, which is doubled for each subsequent retry.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// No retries.
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn is_transient(kind: std::io::ErrorKind) -> bool {
+        matches!(
+            kind,
+            std::io::ErrorKind::Interrupted
+                | std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::WouldBlock
+        )
+    }
+
+    pub fn run<T, F: FnMut() -> Result<T, std::io::Error>>(
+        &self,
+        mut op: F,
+    ) -> Result<T, std::io::Error> {
+        let mut attempt = 0;
+
+        loop {
+            match op() {
+                Err(error) if attempt < self.max_retries && Self::is_transient(error.kind()) => {
+                    std::thread::sleep(self.backoff.saturating_mul(2_u32.saturating_pow(attempt)));
+
+                    attempt += 1;
+                }
+                result => {
+                    return result;
+                }
+            }
+        }
+    }
+}
+
 pub struct ValidatingImporter {
     underlying: Importer,
     hasher: Sha1,
+    retry_policy: RetryPolicy,
+}
+
+impl ValidatingImporter {
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
 }
 
 impl Iterator for ValidatingImporter {
@@ -161,45 +343,205 @@ impl Iterator for ValidatingImporter {
 
     fn next(&mut self) -> Option<Self::Item> {
         self.underlying.next().map(|file| {
-            file.and_then(|file| match file {
-                File::Valid {
-                    path,
-                    compression_type,
-                    digest,
-                } => {
-                    let mut file = std::fs::File::open(&path)?;
+            file.and_then(|file| validate_file(file, &self.retry_policy, &mut self.hasher))
+        })
+    }
+}
 
-                    let computed = match compression_type {
-                        None => digest_bytes(&mut file, &mut self.hasher)?,
-                        Some(CompressionType::Zstd) => {
-                            digest_bytes(&mut zstd::Decoder::new(file)?, &mut self.hasher)?
-                        }
-                        Some(CompressionType::Gzip) => {
-                            digest_bytes(&mut flate2::read::GzDecoder::new(file), &mut self.hasher)?
-                        }
+pub struct ParallelValidatingImporter {
+    state: ParallelState,
+}
+
+enum ParallelState {
+    Pending {
+        underlying: Importer,
+        threads: usize,
+        retry_policy: RetryPolicy,
+    },
+    Running(mpsc::Receiver<Result<File, Error>>),
+}
+
+impl ParallelValidatingImporter {
+    /// Set the retry policy, which has no effect once iteration has started.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        if let ParallelState::Pending {
+            retry_policy: current,
+            ..
+        } = &mut self.state
+        {
+            *current = retry_policy;
+        }
+
+        self
+    }
+
+    fn start(
+        underlying: Importer,
+        threads: usize,
+        retry_policy: RetryPolicy,
+    ) -> mpsc::Receiver<Result<File, Error>> {
+        let (file_sender, file_receiver) = mpsc::sync_channel::<File>(threads.max(1) * 2);
+        let file_receiver = Arc::new(Mutex::new(file_receiver));
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        for _ in 0..threads.max(1) {
+            let file_receiver = file_receiver.clone();
+            let result_sender = result_sender.clone();
+
+            std::thread::spawn(move || {
+                let mut hasher = Sha1::default();
+
+                loop {
+                    // The lock is released before the file is validated.
+                    let file = match file_receiver.lock() {
+                        Ok(file_receiver) => file_receiver.recv(),
+                        Err(_) => break,
                     };
 
-                    if computed == digest {
-                        Ok(File::Valid {
-                            path,
-                            compression_type,
-                            digest,
-                        })
-                    } else {
-                        Err(Error::InvalidDigest {
-                            expected: digest,
-                            found: computed,
-                        })
+                    // Receiving fails when all files have been sent, and sending fails if the receiver has been
+                    // dropped.
+                    let Ok(file) = file else {
+                        break;
+                    };
+
+                    if result_sender
+                        .send(validate_file(file, &retry_policy, &mut hasher))
+                        .is_err()
+                    {
+                        break;
                     }
                 }
-                File::Skipped { path } => Ok(File::Skipped { path }),
-            })
+            });
+        }
+
+        std::thread::spawn(move || {
+            for file in underlying {
+                let sent = match file {
+                    Ok(file) => file_sender.send(file).is_ok(),
+                    Err(error) => result_sender.send(Err(error)).is_ok(),
+                };
+
+                if !sent {
+                    break;
+                }
+            }
+        });
+
+        result_receiver
+    }
+}
+
+impl Iterator for ParallelValidatingImporter {
+    type Item = Result<File, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let ParallelState::Pending {
+            underlying,
+            threads,
+            retry_policy,
+        } = &mut self.state
+        {
+            let underlying = std::mem::replace(underlying, Importer::Failed(None));
+
+            self.state = ParallelState::Running(Self::start(underlying, *threads, *retry_policy));
+        }
+
+        match &self.state {
+            // The channel is closed when all files have been validated.
+            ParallelState::Running(receiver) => receiver.recv().ok(),
+            ParallelState::Pending { .. } => None,
+        }
+    }
+}
+
+fn validate_file(file: File, retry_policy: &RetryPolicy, hasher: &mut Sha1) -> Result<File, Error> {
+    match file {
+        File::Valid {
+            path,
+            compression_type,
+            digest,
+        } => {
+            let computed = retry_policy.run(|| digest_file(&path, compression_type, hasher))?;
+
+            if computed.ct_eq(&digest) {
+                Ok(File::Valid {
+                    path,
+                    compression_type,
+                    digest,
+                })
+            } else {
+                Err(Error::InvalidDigest {
+                    expected: digest,
+                    found: computed,
+                })
+            }
+        }
+        File::Skipped { path } => Ok(File::Skipped { path }),
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VerifyOutcome {
+    Match,
+    Mismatch {
+        expected: Sha1Digest,
+        found: Sha1Digest,
+    },
+    Skipped {
+        reason: &'static str,
+    },
+}
+
+/// Check whether the digest in a single file's name matches its decompressed content.
+pub fn verify_file(path: &Path) -> Result<VerifyOutcome, Error> {
+    if path.is_dir() {
+        Ok(VerifyOutcome::Skipped {
+            reason: "Not a file",
         })
+    } else {
+        match File::new(path) {
+            File::Valid {
+                path,
+                compression_type,
+                digest,
+            } => {
+                let found = digest_file(&path, compression_type, &mut Sha1::default())
+                    .map_err(|error| Error::FileIo(path, error))?;
+
+                if found.ct_eq(&digest) {
+                    Ok(VerifyOutcome::Match)
+                } else {
+                    Ok(VerifyOutcome::Mismatch {
+                        expected: digest,
+                        found,
+                    })
+                }
+            }
+            File::Skipped { .. } => Ok(VerifyOutcome::Skipped {
+                reason: "Unrecognized file name",
+            }),
+        }
+    }
+}
+
+/// Compute the SHA-1 hash for the decompressed contents of a file.
+fn digest_file<P: AsRef<Path>>(
+    path: P,
+    compression_type: Option<CompressionType>,
+    hasher: &mut Sha1,
+) -> Result<Sha1Digest, std::io::Error> {
+    let mut file = std::fs::File::open(path)?;
+
+    match compression_type {
+        None => digest_bytes(&mut file, hasher),
+        Some(compression_type) => digest_bytes(&mut compression_type.decoder(file)?, hasher),
     }
 }
 
 /// Compute the SHA-1 hash for bytes read from a source.
 fn digest_bytes<R: Read>(input: &mut R, hasher: &mut Sha1) -> Result<Sha1Digest, std::io::Error> {
+    // The hasher may contain partial input from a failed attempt.
+    hasher.reset();
     std::io::copy(input, hasher)?;
     hasher.flush()?;
 
@@ -207,3 +549,267 @@ fn digest_bytes<R: Read>(input: &mut R, hasher: &mut Sha1) -> Result<Sha1Digest,
 
     Ok(Sha1Digest(bytes.into()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sha1_digest, snapshot_content};
+
+    struct FlakyReader<'a> {
+        failures: &'a std::cell::Cell<usize>,
+        kind: std::io::ErrorKind,
+        content: &'a [u8],
+    }
+
+    impl Read for FlakyReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            // Fail after the first read, so that the hasher has some partial input.
+            if self.failures.get() > 0 && self.content.len() < 10 {
+                self.failures.set(self.failures.get() - 1);
+
+                Err(std::io::Error::from(self.kind))
+            } else {
+                let len = buf.len().min(self.content.len()).min(10);
+                buf[..len].copy_from_slice(&self.content[..len]);
+                self.content = &self.content[len..];
+
+                Ok(len)
+            }
+        }
+    }
+
+    #[test]
+    fn retry_policy() -> Result<(), Box<dyn std::error::Error>> {
+        let (digest, content) = snapshot_content(1);
+        let policy = RetryPolicy {
+            max_retries: 2,
+            backoff: Duration::ZERO,
+        };
+
+        let mut hasher = Sha1::default();
+        let failures = std::cell::Cell::new(2);
+
+        let result = policy.run(|| {
+            digest_bytes(
+                &mut FlakyReader {
+                    failures: &failures,
+                    kind: std::io::ErrorKind::TimedOut,
+                    content: content.as_bytes(),
+                },
+                &mut hasher,
+            )
+        })?;
+
+        assert_eq!(result, digest);
+
+        let failures = std::cell::Cell::new(3);
+
+        let result = policy.run(|| {
+            digest_bytes(
+                &mut FlakyReader {
+                    failures: &failures,
+                    kind: std::io::ErrorKind::TimedOut,
+                    content: content.as_bytes(),
+                },
+                &mut hasher,
+            )
+        });
+
+        assert_eq!(
+            result.map_err(|error| error.kind()),
+            Err(std::io::ErrorKind::TimedOut)
+        );
+
+        let failures = std::cell::Cell::new(2);
+
+        let result = policy.run(|| {
+            digest_bytes(
+                &mut FlakyReader {
+                    failures: &failures,
+                    kind: std::io::ErrorKind::NotFound,
+                    content: content.as_bytes(),
+                },
+                &mut hasher,
+            )
+        });
+
+        assert_eq!(
+            result.map_err(|error| error.kind()),
+            Err(std::io::ErrorKind::NotFound)
+        );
+        assert_eq!(failures.get(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn par_validating() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut valid_digests = vec![];
+
+        for i in 0..8 {
+            let (digest, content) = snapshot_content(i);
+
+            std::fs::create_dir_all(dir.path().join(i.to_string()))?;
+            std::fs::write(
+                dir.path().join(i.to_string()).join(format!("{digest}.zst")),
+                zstd::encode_all(content.as_bytes(), 3)?,
+            )?;
+
+            valid_digests.push(digest);
+        }
+
+        let corrupted_digest = sha1_digest(b"{\"data\":{}}\r\r\n");
+        std::fs::write(
+            dir.path().join(corrupted_digest.to_string()),
+            b"{\"data\":null}\r\r\n",
+        )?;
+        std::fs::write(dir.path().join("README.md"), b"")?;
+
+        let mut validated_digests = vec![];
+        let mut invalid_digests = vec![];
+        let mut skipped_count = 0;
+
+        for result in Importer::new(dir.path()).par_validating(3) {
+            match result {
+                Ok(File::Valid { digest, .. }) => validated_digests.push(digest),
+                Ok(File::Skipped { .. }) => skipped_count += 1,
+                Err(Error::InvalidDigest { expected, .. }) => invalid_digests.push(expected),
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        validated_digests.sort();
+        valid_digests.sort();
+
+        assert_eq!(validated_digests, valid_digests);
+        assert_eq!(invalid_digests, vec![corrupted_digest]);
+        assert_eq!(skipped_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_sniffed() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let (digest, content) = snapshot_content(1);
+        let zstd_content = zstd::encode_all(content.as_bytes(), 3)?;
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(content.as_bytes())?;
+        let gzip_content = encoder.finish()?;
+
+        let cases = [
+            ("zst", zstd_content.as_slice(), Some(CompressionType::Zstd)),
+            ("", zstd_content.as_slice(), Some(CompressionType::Zstd)),
+            ("zst", gzip_content.as_slice(), Some(CompressionType::Gzip)),
+            ("gz", content.as_bytes(), None),
+        ];
+
+        for (extension, file_content, expected) in cases {
+            let path = if extension.is_empty() {
+                dir.path().join(digest.to_string())
+            } else {
+                dir.path().join(format!("{digest}.{extension}"))
+            };
+
+            std::fs::write(&path, file_content)?;
+
+            match File::new_sniffed(&path)? {
+                File::Valid {
+                    compression_type,
+                    digest: file_digest,
+                    ..
+                } => {
+                    assert_eq!(compression_type, expected);
+                    assert_eq!(file_digest, digest);
+                    assert_eq!(
+                        digest_file(&path, compression_type, &mut Sha1::default())?,
+                        digest
+                    );
+                }
+                File::Skipped { .. } => panic!("Expected valid file"),
+            }
+
+            std::fs::remove_file(&path)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_xz_and_bzip2_files() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let (digest, content) = snapshot_content(1);
+
+        let xz_path = dir.path().join(format!("{digest}.xz"));
+        let mut encoder = xz2::write::XzEncoder::new(std::fs::File::create(&xz_path)?, 6);
+        encoder.write_all(content.as_bytes())?;
+        encoder.finish()?;
+
+        let bzip2_path = dir.path().join(format!("{digest}.bz2"));
+        let mut encoder = bzip2::write::BzEncoder::new(
+            std::fs::File::create(&bzip2_path)?,
+            bzip2::Compression::default(),
+        );
+        encoder.write_all(content.as_bytes())?;
+        encoder.finish()?;
+
+        assert_eq!(super::verify_file(&xz_path)?, VerifyOutcome::Match);
+        assert_eq!(super::verify_file(&bzip2_path)?, VerifyOutcome::Match);
+
+        for (path, expected) in [
+            (xz_path, CompressionType::Xz),
+            (bzip2_path, CompressionType::Bzip2),
+        ] {
+            assert!(matches!(
+                File::new_sniffed(&path)?,
+                File::Valid { compression_type, .. } if compression_type == Some(expected)
+            ));
+        }
+
+        let validated = Importer::new(dir.path())
+            .validating()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(validated.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_file() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let (digest, content) = snapshot_content(1);
+
+        let zstd_path = dir.path().join(format!("{digest}.zst"));
+        std::fs::write(&zstd_path, zstd::encode_all(content.as_bytes(), 3)?)?;
+
+        let gzip_path = dir.path().join(format!("{digest}.gz"));
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&gzip_path)?,
+            flate2::Compression::default(),
+        );
+        encoder.write_all(snapshot_content(2).1.as_bytes())?;
+        encoder.finish()?;
+
+        let other_path = dir.path().join("README.md");
+        std::fs::write(&other_path, &content)?;
+
+        assert_eq!(super::verify_file(&zstd_path)?, VerifyOutcome::Match);
+        assert!(matches!(
+            super::verify_file(&gzip_path)?,
+            VerifyOutcome::Mismatch { expected, found } if expected == digest && found != digest
+        ));
+        assert!(matches!(
+            super::verify_file(&other_path)?,
+            VerifyOutcome::Skipped { .. }
+        ));
+        assert!(matches!(
+            super::verify_file(dir.path())?,
+            VerifyOutcome::Skipped { .. }
+        ));
+
+        Ok(())
+    }
+}
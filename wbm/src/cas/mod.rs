@@ -1 +1,3 @@
+#[cfg(feature = "client")]
+pub mod download;
 pub mod import;
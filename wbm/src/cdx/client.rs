@@ -0,0 +1,286 @@
+//! Async fetching of CDX results from the Wayback Machine.
+
+use crate::cdx::item::{Item, ItemList};
+use crate::cdx::query::{CDX_SEARCH_URL, CdxQuery};
+use futures::{Stream, TryStreamExt};
+use std::time::Duration;
+
+/// Default delay between consecutive requests for pages of results.
+pub const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("HTTP error")]
+    Http(#[from] reqwest::Error),
+    #[error("CDX item error")]
+    Item(#[from] crate::cdx::item::Error),
+}
+
+#[derive(Clone, Debug)]
+pub struct CdxClient {
+    client: reqwest::Client,
+    base_url: String,
+    delay: Duration,
+}
+
+impl Default for CdxClient {
+    fn default() -> Self {
+        Self::new(reqwest::Client::new())
+    }
+}
+
+impl CdxClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            base_url: CDX_SEARCH_URL.to_string(),
+            delay: DEFAULT_DELAY,
+        }
+    }
+
+    /// Use a different CDX server endpoint (for example a mirror or a test server).
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    /// Wait for the given duration before requesting each page after the first in `fetch_all`.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn query_url(&self, query: &CdxQuery) -> String {
+        format!("{}?{}", self.base_url, query.query_string())
+    }
+
+    /// Fetch a single page of results.
+    ///
+    /// Both JSON and plain-text output are supported, depending on the query.
+    pub async fn fetch(&self, query: &CdxQuery) -> Result<ItemList<'static>, Error> {
+        let response = self
+            .client
+            .get(self.query_url(query))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let bytes = response.bytes().await?;
+
+        Ok(if query.is_output_json() {
+            ItemList::from_reader(bytes.as_ref())?
+        } else {
+            ItemList::from_text(bytes.as_ref())?
+        })
+    }
+
+    /// Fetch all pages of results, following resume keys until there are none left.
+    ///
+    /// Resume keys are always requested, but note that the CDX server only provides them for queries with a limit.
+    pub fn fetch_all(
+        &self,
+        query: CdxQuery,
+    ) -> impl Stream<Item = Result<Item<'static>, Error>> + '_ {
+        self.query_with_checkpoints(query, |_| {})
+    }
+
+    /// Fetch all pages of results as in `fetch_all`, starting from the page for the given resume key.
+    ///
+    /// This allows a crawl to be restarted from a key recorded by `query_with_checkpoints`.
+    pub fn query_from_resume_key(
+        &self,
+        query: CdxQuery,
+        resume_key: &str,
+    ) -> impl Stream<Item = Result<Item<'static>, Error>> + '_ {
+        self.fetch_all(query.resume_key(resume_key))
+    }
+
+    /// Fetch all pages of results as in `fetch_all`, calling the callback with each resume key that is followed.
+    ///
+    /// The callback is called just before the page for the key is requested, which only happens after all items from
+    /// the previous page have been consumed, so the last key seen can be passed to `query_from_resume_key` to resume
+    /// an interrupted crawl without missing items.
+    pub fn query_with_checkpoints<'a, F: FnMut(&str) + 'a>(
+        &'a self,
+        query: CdxQuery,
+        on_resume_key: F,
+    ) -> impl Stream<Item = Result<Item<'static>, Error>> + 'a {
+        futures::stream::try_unfold(
+            Some((query.show_resume_key(true), None, on_resume_key)),
+            move |state: Option<(CdxQuery, Option<String>, F)>| async move {
+                match state {
+                    Some((query, resume_key, mut on_resume_key)) => {
+                        let query = match resume_key {
+                            Some(resume_key) => {
+                                if !self.delay.is_zero() {
+                                    tokio::time::sleep(self.delay).await;
+                                }
+
+                                on_resume_key(&resume_key);
+
+                                query.resume_key(&resume_key)
+                            }
+                            None => query,
+                        };
+
+                        let items = self.fetch(&query).await?;
+                        let next = items.resume_key.map(|resume_key| {
+                            (query, Some(resume_key.into_owned()), on_resume_key)
+                        });
+
+                        Ok::<_, Error>(Some((
+                            futures::stream::iter(items.values.into_iter().map(Ok)),
+                            next,
+                        )))
+                    }
+                    None => Ok(None),
+                }
+            },
+        )
+        .try_flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const SECOND_PAGE: &str = r#"[["urlkey","timestamp","original","mimetype","statuscode","digest","length"],
+["com,twitter)/captivedreamer7/status/1891759112888709412","20250218075815","https://twitter.com/captivedreamer7/status/1891759112888709412","application/json","-","M456VNNXXWKJJJXH3NSHG4YZ4EEK7HTN","1269"],
+["com,twitter)/captivedreamer7/status/1891759112888709413","20250218075816","https://twitter.com/captivedreamer7/status/1891759112888709413","application/json","-","DJQ6AK5WYEYYVA4A7CMNQJD644UXUFU2","1700"]]"#;
+
+    /// Serve the first example page, or the second page if a resume key is given, recording the request targets.
+    async fn serve_pages() -> std::io::Result<(String, Arc<Mutex<Vec<String>>>)> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = format!("http://{}/cdx/search/cdx", listener.local_addr()?);
+        let targets = Arc::new(Mutex::new(vec![]));
+        let server_targets = targets.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(count) => request.extend_from_slice(&buffer[..count]),
+                    }
+                }
+
+                let request = String::from_utf8_lossy(&request);
+                let target = request.split(' ').nth(1).unwrap_or_default().to_string();
+
+                let body = if target.contains("resumeKey=") {
+                    SECOND_PAGE
+                } else {
+                    include_str!("../../../examples/cdx/1740396642000000.json")
+                };
+
+                server_targets.lock().unwrap().push(target);
+
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        Ok((base_url, targets))
+    }
+
+    #[tokio::test]
+    async fn fetch_page() -> Result<(), Box<dyn std::error::Error>> {
+        let (base_url, _) = serve_pages().await?;
+        let client = CdxClient::default().base_url(&base_url);
+        let query = CdxQuery::default().url("twitter.com/captivedreamer7/status/*");
+
+        let items = client.fetch(&query).await?;
+
+        assert_eq!(items.values.len(), 100);
+        assert_eq!(
+            items.resume_key.as_deref(),
+            Some(
+                "eJwNxzEOgCAMAMCvuJqYtKViy3MIdGAgGqj6fb3tytk3f5u7jRVKvrw9VoflbkNgevZ7Amkilj0xBoqKCVWWgCH-FTyYWD9RQxSp"
+            )
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetch_all_pages() -> Result<(), Box<dyn std::error::Error>> {
+        let (base_url, targets) = serve_pages().await?;
+        let client = CdxClient::default()
+            .base_url(&base_url)
+            .delay(Duration::from_millis(10));
+        let query = CdxQuery::default()
+            .url("twitter.com/captivedreamer7/status/*")
+            .limit(100);
+
+        let items = client.fetch_all(query).try_collect::<Vec<_>>().await?;
+        let targets = targets.lock().unwrap().clone();
+
+        assert_eq!(items.len(), 102);
+        assert_eq!(
+            items[0].original,
+            "https://twitter.com/captivedreamer7/status/1891374930215809087"
+        );
+        assert_eq!(
+            items[101].original,
+            "https://twitter.com/captivedreamer7/status/1891759112888709413"
+        );
+        assert_eq!(targets.len(), 2);
+        assert!(targets[0].contains("showResumeKey=true"));
+        assert!(!targets[0].contains("resumeKey=eJ"));
+        assert!(targets[1].contains(
+            "resumeKey=eJwNxzEOgCAMAMCvuJqYtKViy3MIdGAgGqj6fb3tytk3f5u7jRVKvrw9VoflbkNgevZ7Amkilj0xBoqKCVWWgCH-FTyYWD9RQxSp"
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resume_from_checkpoint() -> Result<(), Box<dyn std::error::Error>> {
+        let (base_url, targets) = serve_pages().await?;
+        let client = CdxClient::default()
+            .base_url(&base_url)
+            .delay(Duration::from_millis(10));
+        let query = CdxQuery::default()
+            .url("twitter.com/captivedreamer7/status/*")
+            .limit(100);
+
+        let mut resume_keys = vec![];
+        let items = client
+            .query_with_checkpoints(query.clone(), |resume_key| {
+                resume_keys.push(resume_key.to_string())
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        assert_eq!(items.len(), 102);
+        assert_eq!(
+            resume_keys,
+            vec![
+                "eJwNxzEOgCAMAMCvuJqYtKViy3MIdGAgGqj6fb3tytk3f5u7jRVKvrw9VoflbkNgevZ7Amkilj0xBoqKCVWWgCH-FTyYWD9RQxSp"
+            ]
+        );
+
+        let resumed = client
+            .query_from_resume_key(query, &resume_keys[0])
+            .try_collect::<Vec<_>>()
+            .await?;
+        let targets = targets.lock().unwrap().clone();
+
+        assert_eq!(resumed, items[100..]);
+        assert_eq!(targets.len(), 3);
+        assert_eq!(targets[2], targets[1]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+pub const CDX_FIELD_VALUES: [CdxField; 7] = [
+    CdxField::UrlKey,
+    CdxField::Timestamp,
+    CdxField::Original,
+    CdxField::MimeType,
+    CdxField::StatusCode,
+    CdxField::Digest,
+    CdxField::Length,
+];
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum Error {
+    #[error("Unsupported CDX field: {0}")]
+    Unsupported(String),
+}
+
+/// A field in a CDX result, as used in the `fl` query parameter.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum CdxField {
+    UrlKey,
+    Timestamp,
+    Original,
+    MimeType,
+    StatusCode,
+    Digest,
+    Length,
+}
+
+impl CdxField {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::UrlKey => "urlkey",
+            Self::Timestamp => "timestamp",
+            Self::Original => "original",
+            Self::MimeType => "mimetype",
+            Self::StatusCode => "statuscode",
+            Self::Digest => "digest",
+            Self::Length => "length",
+        }
+    }
+}
+
+impl Display for CdxField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CdxField {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "urlkey" => Ok(Self::UrlKey),
+            "timestamp" => Ok(Self::Timestamp),
+            "original" => Ok(Self::Original),
+            "mimetype" => Ok(Self::MimeType),
+            "statuscode" => Ok(Self::StatusCode),
+            "digest" => Ok(Self::Digest),
+            "length" => Ok(Self::Length),
+            other => Err(Self::Err::Unsupported(other.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn round_trip_str() {
+        for field in super::CDX_FIELD_VALUES {
+            let field_str = field.to_string();
+            let parsed = field_str.parse();
+
+            assert_eq!(parsed, Ok(field));
+        }
+    }
+}
@@ -1,6 +1,11 @@
+use crate::cdx::field::{CDX_FIELD_VALUES, CdxField};
 use crate::surt::Surt;
-use serde::de::{Deserialize, Deserializer, IgnoredAny, SeqAccess, Unexpected, Visitor};
+use serde::{
+    de::{Deserialize, Deserializer, IgnoredAny, SeqAccess, Unexpected, Visitor},
+    ser::{Serialize, SerializeSeq, Serializer},
+};
 use std::borrow::Cow;
+use std::io::BufRead;
 
 const EXPECTED_ITEM_LIST_LEN: usize = 10_000;
 const INVALID_LENGTH_MESSAGE: &str = "expected 11 elements";
@@ -39,6 +44,38 @@ impl<'a> ExtendedItem<'a> {
             file_name: self.file_name.into_owned().into(),
         }
     }
+
+    /// The redirect target as an absolute URL.
+    ///
+    /// Relative redirects (starting with `/`) are resolved against the original URL.
+    pub fn redirect_url(&self) -> Result<Option<url::Url>, url::ParseError> {
+        self.redirect
+            .as_ref()
+            .map(|redirect| {
+                if redirect.starts_with('/') {
+                    url::Url::parse(&self.item.original)?.join(redirect)
+                } else {
+                    url::Url::parse(redirect)
+                }
+            })
+            .transpose()
+    }
+
+    /// The SURT for the redirect target.
+    pub fn redirect_surt(&self) -> Result<Option<Surt<'static>>, crate::surt::Error> {
+        self.redirect_url()?
+            .map(|url| Surt::from_url(url.as_str()))
+            .transpose()
+    }
+
+    /// The location of the capture's record in a WARC file, as a file name, offset, and record length.
+    ///
+    /// Returns `None` if the item has no length.
+    pub fn warc_location(&self) -> Option<(String, u64, u64)> {
+        self.item
+            .length
+            .map(|length| (self.file_name.to_string(), self.offset, length.into()))
+    }
 }
 
 // This is an internal representation that we need because of the way resumption keys are given.
@@ -71,9 +108,10 @@ impl<'a, 'de: 'a> Deserialize<'de> for ItemOrEmpty<'a> {
                         let mime_type = seq.next_element()?.ok_or_else(|| {
                             serde::de::Error::invalid_length(3, &INVALID_LENGTH_MESSAGE)
                         })?;
-                        let status_code = seq.next_element()?.ok_or_else(|| {
-                            serde::de::Error::invalid_length(4, &INVALID_LENGTH_MESSAGE)
-                        })?;
+                        let super::StatusCodeValue(status_code) =
+                            seq.next_element()?.ok_or_else(|| {
+                                serde::de::Error::invalid_length(4, &INVALID_LENGTH_MESSAGE)
+                            })?;
                         let digest = seq.next_element()?.ok_or_else(|| {
                             serde::de::Error::invalid_length(5, &INVALID_LENGTH_MESSAGE)
                         })?;
@@ -98,14 +136,10 @@ impl<'a, 'de: 'a> Deserialize<'de> for ItemOrEmpty<'a> {
                             Some(robot_flags_str)
                         };
 
-                        let length_str: &str = seq
+                        let super::LengthValue(length) = seq
                             .next_element()?
                             .ok_or_else(|| serde::de::Error::invalid_length(8, &self))?;
 
-                        let length = super::parse_length(length_str).ok_or_else(|| {
-                            serde::de::Error::invalid_value(Unexpected::Str(length_str), &"length")
-                        })?;
-
                         let offset_str: &str = seq.next_element()?.ok_or_else(|| {
                             serde::de::Error::invalid_length(9, &INVALID_LENGTH_MESSAGE)
                         })?;
@@ -155,6 +189,58 @@ pub struct ExtendedItemList<'a> {
     pub resume_key: Option<Cow<'a, str>>,
 }
 
+impl ExtendedItemList<'_> {
+    /// The query for the next page of results, if there is one (see `ItemList::next_page_query`).
+    pub fn next_page_query(&self, base_query: &str) -> Option<String> {
+        self.resume_key
+            .as_ref()
+            .map(|resume_key| super::append_resume_key(base_query, resume_key))
+    }
+}
+
+impl ExtendedItemList<'static> {
+    /// Parse a plain-text CDX result in the eleven-field extended layout, with one item per line.
+    ///
+    /// A resume key may be given on a line after a blank line.
+    pub fn from_text<R: BufRead>(reader: R) -> Result<Self, super::Error> {
+        let (values, resume_key) = super::read_text_lines(reader, |fields| match fields {
+            [
+                key,
+                timestamp,
+                original,
+                mime_type,
+                status_code,
+                digest,
+                redirect,
+                robot_flags,
+                length,
+                offset,
+                file_name,
+            ] => Some(ExtendedItem {
+                item: super::parse_text_item(&[
+                    *key,
+                    *timestamp,
+                    *original,
+                    *mime_type,
+                    *status_code,
+                    *digest,
+                    *length,
+                ])?,
+                redirect: (*redirect != "-").then(|| redirect.to_string().into()),
+                robot_flags: (*robot_flags != "-").then(|| robot_flags.to_string().into()),
+                offset: offset.parse().ok()?,
+                file_name: file_name.to_string().into(),
+            }),
+            _ => None,
+        })?;
+
+        Ok(Self {
+            values,
+            resume_key: resume_key.map(Cow::Owned),
+        })
+    }
+}
+
 impl<'a, 'de: 'a> Deserialize<'de> for ExtendedItemList<'a> {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         struct EntryListVisitor;
@@ -216,6 +302,48 @@ impl<'a, 'de: 'a> Deserialize<'de> for ExtendedItemList<'a> {
     }
 }
 
+/// Serializes in the Wayback Machine's CDX JSON format (with all extended fields).
+impl Serialize for ExtendedItemList<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let resume_key_len = if self.resume_key.is_some() { 2 } else { 0 };
+        let mut seq = serializer.serialize_seq(Some(self.values.len() + 1 + resume_key_len))?;
+
+        seq.serialize_element(&ITEM_LIST_HEADER)?;
+
+        for item in &self.values {
+            seq.serialize_element(&ExtendedItemRow(item))?;
+        }
+
+        if let Some(resume_key) = &self.resume_key {
+            seq.serialize_element::<[&str; 0]>(&[])?;
+            seq.serialize_element(&[resume_key])?;
+        }
+
+        seq.end()
+    }
+}
+
+struct ExtendedItemRow<'b, 'a>(&'b ExtendedItem<'a>);
+
+impl Serialize for ExtendedItemRow<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(ITEM_LIST_HEADER.len()))?;
+
+        // The first six fields are the same as in the non-extended format.
+        for field in &CDX_FIELD_VALUES[0..6] {
+            seq.serialize_element(&self.0.item.field_value(*field))?;
+        }
+
+        seq.serialize_element(self.0.redirect.as_deref().unwrap_or("-"))?;
+        seq.serialize_element(self.0.robot_flags.as_deref().unwrap_or("-"))?;
+        seq.serialize_element(&self.0.item.field_value(CdxField::Length))?;
+        seq.serialize_element(&self.0.offset.to_string())?;
+        seq.serialize_element(&self.0.file_name)?;
+
+        seq.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -234,4 +362,98 @@ mod tests {
 
         assert_eq!(items.values.len(), 8838);
     }
+
+    #[test]
+    fn round_trip_serialize() {
+        let contents = include_str!("../../../../examples/cdx/1702374488385081.json");
+        let items = serde_json::from_str::<super::ExtendedItemList>(contents).unwrap();
+        let serialized = serde_json::to_string(&items).unwrap();
+        let parsed = serde_json::from_str::<super::ExtendedItemList>(&serialized).unwrap();
+
+        assert_eq!(parsed.values, items.values);
+        assert_eq!(parsed.resume_key, items.resume_key);
+    }
+
+    #[test]
+    fn from_text() {
+        let contents = "com,twitter)/jack/status/20 20060321205050 https://twitter.com/jack/status/20 text/html 200 ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4 - - 1234 5678 example.warc.gz
+com,twitter)/jack 20060321205051 https://twitter.com/jack text/html 301 VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5 https://twitter.com/jack/ noindex - 9012 other.warc.gz
+";
+
+        let items = super::ExtendedItemList::from_text(contents.as_bytes()).unwrap();
+
+        assert_eq!(items.values.len(), 2);
+        assert_eq!(
+            items.values[0].warc_location(),
+            Some(("example.warc.gz".to_string(), 5678, 1234))
+        );
+        assert_eq!(items.values[0].redirect, None);
+        assert_eq!(
+            items.values[1].redirect.as_deref(),
+            Some("https://twitter.com/jack/")
+        );
+        assert_eq!(items.values[1].robot_flags.as_deref(), Some("noindex"));
+        assert_eq!(items.values[1].warc_location(), None);
+        assert_eq!(items.resume_key, None);
+
+        let short = "com,twitter)/jack/status/20 20060321205050 https://twitter.com/jack/status/20 text/html 200 ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4 1234\n";
+
+        assert!(super::ExtendedItemList::from_text(short.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn redirect_url() {
+        let contents = "com,twitter)/jack 20060321205051 https://twitter.com/jack text/html 301 VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5 https://x.com/jack/ - - 9012 example.warc.gz
+com,twitter)/jack 20060321205052 https://twitter.com/jack?lang=en text/html 302 VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5 /jack/status/20 - - 9012 example.warc.gz
+com,twitter)/jack/status/20 20060321205050 https://twitter.com/jack/status/20 text/html 200 ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4 - - 1234 5678 example.warc.gz
+";
+
+        let items = super::ExtendedItemList::from_text(contents.as_bytes()).unwrap();
+
+        assert_eq!(
+            items.values[0].redirect_url().unwrap().unwrap().as_str(),
+            "https://x.com/jack/"
+        );
+        assert_eq!(
+            items.values[0]
+                .redirect_surt()
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            "com,x)/jack"
+        );
+        assert_eq!(
+            items.values[1].redirect_url().unwrap().unwrap().as_str(),
+            "https://twitter.com/jack/status/20"
+        );
+        assert_eq!(
+            items.values[1]
+                .redirect_surt()
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            "com,twitter)/jack/status/20"
+        );
+        assert_eq!(items.values[2].redirect_url(), Ok(None));
+
+        // The example file's only redirect value is neither an absolute URL nor a relative path.
+        let contents = include_str!("../../../../examples/cdx/1702374488385081.json");
+        let items = serde_json::from_str::<super::ExtendedItemList>(contents).unwrap();
+
+        assert!(items.values[6948].redirect_url().is_err());
+    }
+
+    #[test]
+    fn warc_location() {
+        let contents = include_str!("../../../../examples/cdx/1702374488385081.json");
+        let items = serde_json::from_str::<super::ExtendedItemList>(contents).unwrap();
+
+        let expected = (
+            "archiveteam_archivebot_go_20190622080001/urls-transfer.notkiska.pw-twitter-%23antifa-shallow-20190621-153959-d789r-00005.warc.gz".to_string(),
+            1500768660,
+            71613,
+        );
+
+        assert_eq!(items.values[0].warc_location(), Some(expected));
+    }
 }
@@ -1,12 +1,24 @@
-use crate::cdx::{mime_type::MimeType, status_code::StatusCode};
+use crate::cdx::{
+    field::{CDX_FIELD_VALUES, CdxField},
+    mime_type::MimeType,
+    status_code::StatusCode,
+};
 use crate::{
     digest::Digest,
     item::{ItemInfo, UrlParts},
     surt::Surt,
-    timestamp::Timestamp,
+    timestamp::{Timestamp, TimestampRange},
+};
+use serde::{
+    de::{Deserialize, Deserializer, IgnoredAny, SeqAccess, Unexpected, Visitor},
+    ser::{Serialize, SerializeSeq, Serializer},
 };
-use serde::de::{Deserialize, Deserializer, IgnoredAny, SeqAccess, Unexpected, Visitor};
 use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
 
 pub mod extended;
 
@@ -22,12 +34,23 @@ const ITEM_LIST_HEADER: [&str; 7] = [
     "length",
 ];
 
+/// Maximum difference allowed by `Item::verify_length` between the CDX length and a fetched size.
+///
+/// The CDX length includes compressed record and HTTP headers, so we need some slack.
+pub const LENGTH_TOLERANCE: u64 = 2048;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
     #[error("JSON decoding error: {0}")]
     Json(#[from] serde_json::Error),
     #[error("Invalid MIME type")]
     InvalidMimeType(#[from] crate::cdx::mime_type::Error),
+    #[error("Too many items (limit: {0})")]
+    TooManyItems(usize),
+    #[error("Invalid CDX text line: {0}")]
+    InvalidTextLine(String),
 }
 
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
@@ -54,6 +77,35 @@ impl<'a> Item<'a> {
         }
     }
 
+    /// Check whether the compressed size of a fetched capture is consistent with the CDX length.
+    ///
+    /// The `length` field in Wayback Machine CDX results is the size in bytes of the compressed WARC (or ARC) record
+    /// that stores the capture. This record includes the WARC record headers and HTTP response headers as well as
+    /// the body, so the values will not usually be exactly equal, but a large difference (for example because of a
+    /// truncated download) indicates that the capture should not be trusted.
+    ///
+    /// Returns `true` if there is no length for this item.
+    pub fn verify_length(&self, actual_compressed_len: u64) -> bool {
+        self.length.is_none_or(|length| {
+            u64::from(length).abs_diff(actual_compressed_len) <= LENGTH_TOLERANCE
+        })
+    }
+
+    /// The value of the given field as it would appear in a CDX result.
+    pub fn field_value(&self, field: CdxField) -> Cow<'_, str> {
+        match field {
+            CdxField::UrlKey => self.key.as_str().into(),
+            CdxField::Timestamp => self.timestamp.to_string().into(),
+            CdxField::Original => self.original.as_ref().into(),
+            CdxField::MimeType => self.mime_type.as_str().into(),
+            CdxField::StatusCode => self.status_code.as_str(),
+            CdxField::Digest => self.digest.to_string().into(),
+            CdxField::Length => self
+                .length
+                .map_or_else(|| "-".into(), |length| length.to_string().into()),
+        }
+    }
+
     pub fn entry_info(&self) -> ItemInfo<'a> {
         ItemInfo {
             url_parts: UrlParts {
@@ -95,20 +147,17 @@ impl<'a, 'de: 'a> Deserialize<'de> for ItemOrEmpty<'a> {
                         let mime_type = seq.next_element()?.ok_or_else(|| {
                             serde::de::Error::invalid_length(3, &INVALID_LENGTH_MESSAGE)
                         })?;
-                        let status_code = seq.next_element()?.ok_or_else(|| {
-                            serde::de::Error::invalid_length(4, &INVALID_LENGTH_MESSAGE)
-                        })?;
+                        let StatusCodeValue(status_code) =
+                            seq.next_element()?.ok_or_else(|| {
+                                serde::de::Error::invalid_length(4, &INVALID_LENGTH_MESSAGE)
+                            })?;
                         let digest = seq.next_element()?.ok_or_else(|| {
                             serde::de::Error::invalid_length(5, &INVALID_LENGTH_MESSAGE)
                         })?;
-                        let length_str: &str = seq.next_element()?.ok_or_else(|| {
+                        let LengthValue(length) = seq.next_element()?.ok_or_else(|| {
                             serde::de::Error::invalid_length(6, &INVALID_LENGTH_MESSAGE)
                         })?;
 
-                        let length = parse_length(length_str).ok_or_else(|| {
-                            serde::de::Error::invalid_value(Unexpected::Str(length_str), &"length")
-                        })?;
-
                         let end: Option<IgnoredAny> = seq.next_element()?;
 
                         match end {
@@ -139,64 +188,693 @@ pub struct ItemList<'a> {
     pub resume_key: Option<Cow<'a, str>>,
 }
 
-impl<'a, 'de: 'a> Deserialize<'de> for ItemList<'a> {
-    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        struct EntryListVisitor;
+impl<'a> ItemList<'a> {
+    pub fn into_owned(self) -> ItemList<'static> {
+        ItemList {
+            values: self.values.into_iter().map(Item::into_owned).collect(),
+            resume_key: self
+                .resume_key
+                .map(|resume_key| resume_key.into_owned().into()),
+        }
+    }
 
-        impl<'de> Visitor<'de> for EntryListVisitor {
-            type Value = ItemList<'de>;
+    /// Write the list in the Wayback Machine's CDX JSON format, including only the given fields (in order).
+    ///
+    /// The resume key is written after an empty row if present, as in the Wayback Machine's paginated results.
+    pub fn serialize_fields<W: Write>(
+        &self,
+        fields: &[CdxField],
+        mut writer: W,
+    ) -> Result<(), Error> {
+        let header = fields
+            .iter()
+            .map(|field| field.as_str())
+            .collect::<Vec<_>>();
 
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("struct ItemList")
+        writer.write_all(b"[")?;
+        serde_json::to_writer(&mut writer, &header)?;
+
+        for item in &self.values {
+            let row = fields
+                .iter()
+                .map(|field| item.field_value(*field))
+                .collect::<Vec<_>>();
+
+            writer.write_all(b",\n")?;
+            serde_json::to_writer(&mut writer, &row)?;
+        }
+
+        if let Some(resume_key) = &self.resume_key {
+            writer.write_all(b",\n[],\n")?;
+            serde_json::to_writer(&mut writer, &[resume_key])?;
+        }
+
+        writer.write_all(b"]\n")?;
+
+        Ok(())
+    }
+
+    /// Group capture timestamps by original URL.
+    ///
+    /// The timestamps for each URL are sorted in ascending order.
+    pub fn captures_by_url(&self) -> BTreeMap<&str, Vec<Timestamp>> {
+        let mut captures: BTreeMap<&str, Vec<Timestamp>> = BTreeMap::new();
+
+        for item in &self.values {
+            captures
+                .entry(item.original.as_ref())
+                .or_default()
+                .push(item.timestamp);
+        }
+
+        for timestamps in captures.values_mut() {
+            timestamps.sort_unstable();
+        }
+
+        captures
+    }
+
+    /// Check the SURT keys of JSON items against keys computed from their original URLs.
+    ///
+    /// MIME type parameters are ignored. Returns the number of matching keys and the mismatches as pairs of the stored
+    /// and computed keys.
+    pub fn check_json_surts(
+        &self,
+    ) -> Result<(usize, Vec<(Surt<'a>, Surt<'static>)>), crate::surt::Error> {
+        let mut match_count = 0;
+        let mut mismatches = vec![];
+
+        for item in self.filter().mime_type(MimeType::ApplicationJson).iter() {
+            let computed = Surt::from_url(&item.original)?;
+
+            if computed == item.key {
+                match_count += 1;
+            } else {
+                mismatches.push((item.key.clone(), computed));
             }
+        }
 
-            fn visit_seq<V: SeqAccess<'de>>(self, mut seq: V) -> Result<Self::Value, V::Error> {
-                match seq.next_element::<Vec<&str>>()? {
-                    Some(header) => {
-                        if header == ITEM_LIST_HEADER {
-                            let mut values = Vec::with_capacity(EXPECTED_ITEM_LIST_LEN);
-
-                            let mut expect_resume_key = false;
-
-                            while let Some(next) = seq.next_element::<ItemOrEmpty>()? {
-                                match next {
-                                    ItemOrEmpty::Item(item) => {
-                                        values.push(item);
-                                    }
-                                    ItemOrEmpty::Empty => {
-                                        expect_resume_key = true;
-                                        break;
-                                    }
+        Ok((match_count, mismatches))
+    }
+}
+
+impl<'a> ItemList<'a> {
+    /// The query for the next page of results, if there is one.
+    ///
+    /// The resume key is percent-encoded and appended to the given CDX query string.
+    pub fn next_page_query(&self, base_query: &str) -> Option<String> {
+        self.resume_key
+            .as_ref()
+            .map(|resume_key| append_resume_key(base_query, resume_key))
+    }
+
+    /// Keep only the earliest capture for each valid digest.
+    ///
+    /// Items with invalid digests are always kept, and the order of the remaining items is preserved. If there are
+    /// multiple captures with the earliest timestamp, the first is kept.
+    pub fn dedup_by_digest(&self) -> Vec<Item<'a>> {
+        let mut earliest: HashMap<_, usize> = HashMap::new();
+
+        for (index, item) in self.values.iter().enumerate() {
+            if let Some(digest) = item.digest.valid() {
+                earliest
+                    .entry(digest)
+                    .and_modify(|current| {
+                        if item.timestamp < self.values[*current].timestamp {
+                            *current = index;
+                        }
+                    })
+                    .or_insert(index);
+            }
+        }
+
+        self.values
+            .iter()
+            .enumerate()
+            .filter(|(index, item)| {
+                item.digest
+                    .valid()
+                    .is_none_or(|digest| earliest.get(&digest) == Some(index))
+            })
+            .map(|(_, item)| item.clone())
+            .collect()
+    }
+
+    /// Start building a filter over the items in this list.
+    pub fn filter<'b>(&'b self) -> ItemFilter<'b, 'a> {
+        ItemFilter {
+            items: &self.values,
+            mime_type: None,
+            status_code: None,
+            timestamp_range: None,
+            min_length: None,
+        }
+    }
+
+    /// Parse a JSON CDX result, failing with `Error::TooManyItems` if it contains more than `max_items` items.
+    ///
+    /// This should be used instead of the `Deserialize` instance (which does not limit the number of items) for
+    /// untrusted input.
+    pub fn parse_json_with_max_items(input: &'a str, max_items: usize) -> Result<Self, Error> {
+        let exceeded = Cell::new(false);
+        let mut deserializer = serde_json::Deserializer::from_str(input);
+
+        (&mut deserializer)
+            .deserialize_seq(ItemListVisitor {
+                max_items,
+                exceeded: &exceeded,
+            })
+            .and_then(|items| deserializer.end().map(|()| items))
+            .map_err(|error| {
+                if exceeded.get() {
+                    Error::TooManyItems(max_items)
+                } else {
+                    Error::from(error)
+                }
+            })
+    }
+}
+
+/// A filter over the items in a list, where all given conditions must hold.
+pub struct ItemFilter<'b, 'a> {
+    items: &'b [Item<'a>],
+    mime_type: Option<MimeType<'b>>,
+    status_code: Option<StatusCode>,
+    timestamp_range: Option<TimestampRange>,
+    min_length: Option<u32>,
+}
+
+impl<'b, 'a> ItemFilter<'b, 'a> {
+    pub fn mime_type(mut self, mime_type: MimeType<'b>) -> Self {
+        self.mime_type = Some(mime_type);
+        self
+    }
+
+    pub fn status_code(mut self, status_code: StatusCode) -> Self {
+        self.status_code = Some(status_code);
+        self
+    }
+
+    pub fn timestamp_range(mut self, timestamp_range: TimestampRange) -> Self {
+        self.timestamp_range = Some(timestamp_range);
+        self
+    }
+
+    /// Only include items with a known length that is at least the given value.
+    pub fn min_length(mut self, min_length: u32) -> Self {
+        self.min_length = Some(min_length);
+        self
+    }
+
+    pub fn matches(&self, item: &Item<'_>) -> bool {
+        self.mime_type.as_ref().is_none_or(|mime_type| {
+            // A filter without parameters matches any parameters.
+            item.mime_type.base() == mime_type.base()
+                && mime_type
+                    .params()
+                    .is_none_or(|params| item.mime_type.params() == Some(params))
+        }) && self
+            .status_code
+            .is_none_or(|status_code| item.status_code == status_code)
+            && self
+                .timestamp_range
+                .is_none_or(|timestamp_range| timestamp_range.contains(item.timestamp))
+            && self
+                .min_length
+                .is_none_or(|min_length| item.length.is_some_and(|length| length >= min_length))
+    }
+
+    /// The matching items, without copying them.
+    pub fn iter(&self) -> impl Iterator<Item = &'b Item<'a>> + '_ {
+        self.items.iter().filter(|item| self.matches(item))
+    }
+
+    pub fn collect(self) -> Vec<Item<'a>> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl ItemList<'static> {
+    /// Read a JSON CDX result that may be gzip or zstd compressed.
+    ///
+    /// The compression type is detected from the first bytes of the input.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        let mut reader = BufReader::new(reader);
+        let start = reader.fill_buf()?;
+        let is_gzip = start.starts_with(&crate::cdx::GZIP_MAGIC);
+        let is_zstd = start.starts_with(&crate::cdx::ZSTD_MAGIC);
+
+        let contents = if is_gzip {
+            std::io::read_to_string(flate2::read::GzDecoder::new(reader))?
+        } else if is_zstd {
+            std::io::read_to_string(zstd::Decoder::with_buffer(reader)?)?
+        } else {
+            std::io::read_to_string(reader)?
+        };
+
+        Ok(serde_json::from_str::<ItemList>(&contents)?.into_owned())
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+
+    /// Parse a plain-text CDX result, with one whitespace-delimited item per line.
+    ///
+    /// Both the default seven-field layout and the eleven-field extended layout are accepted (the extended fields are
+    /// discarded; see `ExtendedItemList::from_text`). A resume key may be given on a line after a blank line.
+    pub fn from_text<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let (values, resume_key) = read_text_lines(reader, |fields| match fields.len() {
+            7 => parse_text_item(fields),
+            11 => parse_text_item(&[
+                fields[0], fields[1], fields[2], fields[3], fields[4], fields[5], fields[8],
+            ]),
+            _ => None,
+        })?;
+
+        Ok(Self {
+            values,
+            resume_key: resume_key.map(Cow::Owned),
+        })
+    }
+}
+
+/// Parse the seven standard fields of a plain-text CDX line.
+fn parse_text_item(fields: &[&str]) -> Option<Item<'static>> {
+    match fields {
+        [
+            key,
+            timestamp,
+            original,
+            mime_type,
+            status_code,
+            digest,
+            length,
+        ] => Some(Item {
+            key: key.parse().ok()?,
+            timestamp: timestamp.parse().ok()?,
+            original: original.to_string().into(),
+            mime_type: mime_type.parse().ok()?,
+            status_code: status_code.parse().ok()?,
+            digest: digest.parse().ok()?,
+            length: parse_length(length)?,
+        }),
+        _ => None,
+    }
+}
+
+/// Read plain-text CDX lines, returning the parsed values and the resume key (if any).
+fn read_text_lines<R: BufRead, T, F: FnMut(&[&str]) -> Option<T>>(
+    reader: R,
+    mut parse: F,
+) -> Result<(Vec<T>, Option<String>), Error> {
+    let mut values = vec![];
+    let mut resume_key = None;
+    let mut after_blank = false;
+
+    for line in reader.lines() {
+        let line = line?;
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+
+        if fields.is_empty() {
+            after_blank = true;
+        } else if after_blank {
+            // Only a single resume key is allowed after a blank line.
+            if fields.len() == 1 && resume_key.is_none() {
+                resume_key = Some(fields[0].to_string());
+            } else {
+                return Err(Error::InvalidTextLine(line));
+            }
+        } else {
+            match parse(&fields) {
+                Some(value) => values.push(value),
+                None => return Err(Error::InvalidTextLine(line)),
+            }
+        }
+    }
+
+    Ok((values, resume_key))
+}
+
+struct ItemListVisitor<'c> {
+    max_items: usize,
+    // Allows us to distinguish the item limit being reached from other errors.
+    exceeded: &'c Cell<bool>,
+}
+
+impl<'de> Visitor<'de> for ItemListVisitor<'_> {
+    type Value = ItemList<'de>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("struct ItemList")
+    }
+
+    fn visit_seq<V: SeqAccess<'de>>(self, mut seq: V) -> Result<Self::Value, V::Error> {
+        match seq.next_element::<Vec<&str>>()? {
+            Some(header) => {
+                if header == ITEM_LIST_HEADER {
+                    let mut values = Vec::with_capacity(EXPECTED_ITEM_LIST_LEN.min(self.max_items));
+
+                    let mut expect_resume_key = false;
+
+                    while let Some(next) = seq.next_element::<ItemOrEmpty>()? {
+                        match next {
+                            ItemOrEmpty::Item(item) => {
+                                if values.len() >= self.max_items {
+                                    self.exceeded.set(true);
+
+                                    return Err(serde::de::Error::custom("too many items"));
                                 }
-                            }
 
-                            let resume_key = if expect_resume_key {
-                                let (resume_key,) = seq
-                                    .next_element()?
-                                    .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
-
-                                Some(resume_key)
-                            } else {
-                                None
-                            };
-
-                            Ok(ItemList { values, resume_key })
-                        } else {
-                            Err(serde::de::Error::invalid_value(
-                                Unexpected::Seq,
-                                &"CDX item list header",
-                            ))
+                                values.push(item);
+                            }
+                            ItemOrEmpty::Empty => {
+                                expect_resume_key = true;
+                                break;
+                            }
                         }
                     }
-                    None => Ok(ItemList {
-                        values: vec![],
-                        resume_key: None,
-                    }),
+
+                    let resume_key = if expect_resume_key {
+                        let (resume_key,) = seq
+                            .next_element()?
+                            .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+
+                        Some(resume_key)
+                    } else {
+                        None
+                    };
+
+                    Ok(ItemList { values, resume_key })
+                } else {
+                    Err(serde::de::Error::invalid_value(
+                        Unexpected::Seq,
+                        &"CDX item list header",
+                    ))
+                }
+            }
+            None => Ok(ItemList {
+                values: vec![],
+                resume_key: None,
+            }),
+        }
+    }
+}
+
+impl<'a, 'de: 'a> Deserialize<'de> for ItemList<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(ItemListVisitor {
+            max_items: usize::MAX,
+            exceeded: &Cell::new(false),
+        })
+    }
+}
+
+fn append_resume_key(base_query: &str, resume_key: &str) -> String {
+    let separator = if base_query.is_empty() || base_query.ends_with(['?', '&']) {
+        ""
+    } else {
+        "&"
+    };
+
+    format!(
+        "{base_query}{separator}resumeKey={}",
+        url::form_urlencoded::byte_serialize(resume_key.as_bytes()).collect::<String>()
+    )
+}
+
+/// Merge lists of items that are already sorted into a single sorted list.
+///
+/// Equal items (for example from overlapping pages) are only included once. The resume keys are ignored.
+pub fn merge_sorted(lists: Vec<ItemList<'_>>) -> Vec<Item<'_>> {
+    let mut iters = lists
+        .into_iter()
+        .map(|list| list.values.into_iter())
+        .collect::<Vec<_>>();
+    let mut heap = BinaryHeap::with_capacity(iters.len());
+
+    for (index, iter) in iters.iter_mut().enumerate() {
+        if let Some(item) = iter.next() {
+            heap.push(Reverse((item, index)));
+        }
+    }
+
+    let mut values: Vec<Item> = Vec::new();
+
+    while let Some(Reverse((item, index))) = heap.pop() {
+        if let Some(next) = iters[index].next() {
+            heap.push(Reverse((next, index)));
+        }
+
+        if values.last() != Some(&item) {
+            values.push(item);
+        }
+    }
+
+    values
+}
+
+/// Serializes in the Wayback Machine's CDX JSON format (with all fields).
+impl Serialize for ItemList<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let resume_key_len = if self.resume_key.is_some() { 2 } else { 0 };
+        let mut seq = serializer.serialize_seq(Some(self.values.len() + 1 + resume_key_len))?;
+
+        seq.serialize_element(&ITEM_LIST_HEADER)?;
+
+        for item in &self.values {
+            seq.serialize_element(&ItemRow(item))?;
+        }
+
+        if let Some(resume_key) = &self.resume_key {
+            seq.serialize_element::<[&str; 0]>(&[])?;
+            seq.serialize_element(&[resume_key])?;
+        }
+
+        seq.end()
+    }
+}
+
+struct ItemRow<'b, 'a>(&'b Item<'a>);
+
+impl Serialize for ItemRow<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(CDX_FIELD_VALUES.len()))?;
+
+        for field in CDX_FIELD_VALUES {
+            seq.serialize_element(&self.0.field_value(field))?;
+        }
+
+        seq.end()
+    }
+}
+
+/// Stream items from a JSON CDX result without reading the entire result into memory.
+pub fn stream_items<R: Read>(reader: R) -> ItemStream<BufReader<R>> {
+    ItemStream::new(BufReader::new(reader))
+}
+
+/// An iterator over the items in a JSON CDX result.
+///
+/// The resume key (if any) is available from `resume_key` once the iterator has been exhausted.
+pub struct ItemStream<R> {
+    reader: R,
+    state: ItemStreamState,
+    resume_key: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ItemStreamState {
+    Start,
+    Items,
+    Done,
+}
+
+impl<R: BufRead> ItemStream<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: ItemStreamState::Start,
+            resume_key: None,
+        }
+    }
+
+    pub fn resume_key(&self) -> Option<&str> {
+        self.resume_key.as_deref()
+    }
+
+    /// Skip whitespace and return the next byte without consuming it.
+    fn peek_byte(&mut self) -> Result<Option<u8>, Error> {
+        loop {
+            let buffer = self.reader.fill_buf()?;
+
+            match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+                Some(index) => {
+                    let byte = buffer[index];
+                    self.reader.consume(index);
+
+                    return Ok(Some(byte));
                 }
+                None if buffer.is_empty() => {
+                    return Ok(None);
+                }
+                None => {
+                    let len = buffer.len();
+                    self.reader.consume(len);
+                }
+            }
+        }
+    }
+
+    /// Skip whitespace and consume the next byte, which must be one of the given values.
+    fn expect_byte(&mut self, expected: &[u8]) -> Result<u8, Error> {
+        match self.peek_byte()? {
+            Some(byte) if expected.contains(&byte) => {
+                self.reader.consume(1);
+
+                Ok(byte)
+            }
+            Some(byte) => Err(Error::Json(serde::de::Error::invalid_value(
+                Unexpected::Char(byte.into()),
+                &"CDX item list delimiter",
+            ))),
+            None => Err(Error::Json(serde::de::Error::custom(
+                "unexpected end of input",
+            ))),
+        }
+    }
+
+    /// Deserialize a single JSON value from the underlying reader.
+    ///
+    /// This relies on the fact that the JSON deserializer does not read past the end of an array.
+    fn read_value<T: Deserialize<'static>>(&mut self) -> Result<T, Error> {
+        let mut deserializer = serde_json::Deserializer::from_reader(&mut self.reader);
+
+        Ok(T::deserialize(&mut deserializer)?)
+    }
+
+    fn next_item(&mut self) -> Result<Option<Item<'static>>, Error> {
+        if self.state == ItemStreamState::Start {
+            self.expect_byte(b"[")?;
+
+            if self.peek_byte()? == Some(b']') {
+                self.reader.consume(1);
+                self.state = ItemStreamState::Done;
+
+                return Ok(None);
+            }
+
+            let header = self.read_value::<Vec<String>>()?;
+
+            if header != ITEM_LIST_HEADER {
+                return Err(Error::Json(serde::de::Error::invalid_value(
+                    Unexpected::Seq,
+                    &"CDX item list header",
+                )));
+            }
+
+            self.state = ItemStreamState::Items;
+        }
+
+        if self.state == ItemStreamState::Done || self.expect_byte(b",]")? == b']' {
+            self.state = ItemStreamState::Done;
+
+            return Ok(None);
+        }
+
+        match self.read_value::<ItemOrEmpty<'static>>()? {
+            ItemOrEmpty::Item(item) => Ok(Some(item)),
+            ItemOrEmpty::Empty => {
+                self.state = ItemStreamState::Done;
+                self.expect_byte(b",")?;
+
+                let (resume_key,) = self.read_value::<(String,)>()?;
+                self.resume_key = Some(resume_key);
+
+                self.expect_byte(b"]")?;
+
+                Ok(None)
             }
         }
+    }
+}
+
+impl<R: BufRead> Iterator for ItemStream<R> {
+    type Item = Result<Item<'static>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_item().transpose();
+
+        // We stop after any error, since the stream position is unknown.
+        if let Some(Err(_)) = result {
+            self.state = ItemStreamState::Done;
+        }
 
-        deserializer.deserialize_seq(EntryListVisitor)
+        result
+    }
+}
+
+/// A status code that may be encoded as either a string or a number.
+struct StatusCodeValue(StatusCode);
+
+impl<'de> Deserialize<'de> for StatusCodeValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StatusCodeValueVisitor;
+
+        impl<'de> Visitor<'de> for StatusCodeValueVisitor {
+            type Value = StatusCodeValue;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("status code string or number")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse()
+                    .map(StatusCodeValue)
+                    .map_err(|_| serde::de::Error::invalid_value(Unexpected::Str(v), &self))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                u16::try_from(v)
+                    .ok()
+                    .and_then(|value| StatusCode::from_value(value).ok())
+                    .map(StatusCodeValue)
+                    .ok_or_else(|| serde::de::Error::invalid_value(Unexpected::Unsigned(v), &self))
+            }
+        }
+
+        deserializer.deserialize_any(StatusCodeValueVisitor)
+    }
+}
+
+/// A length that may be encoded as either a string (possibly `-`) or a number.
+struct LengthValue(Option<u32>);
+
+impl<'de> Deserialize<'de> for LengthValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct LengthValueVisitor;
+
+        impl<'de> Visitor<'de> for LengthValueVisitor {
+            type Value = LengthValue;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("length string or number")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                parse_length(v)
+                    .map(LengthValue)
+                    .ok_or_else(|| serde::de::Error::invalid_value(Unexpected::Str(v), &self))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .map(|value| LengthValue(Some(value)))
+                    .map_err(|_| serde::de::Error::invalid_value(Unexpected::Unsigned(v), &self))
+            }
+        }
+
+        deserializer.deserialize_any(LengthValueVisitor)
     }
 }
 
@@ -227,6 +905,326 @@ mod tests {
         assert_eq!(items.values.len(), 37647);
     }
 
+    #[test]
+    fn stream_items() {
+        let file = std::fs::File::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../examples/cdx/1706619334645856.json"
+        ))
+        .unwrap();
+        let contents = include_str!("../../../../examples/cdx/1706619334645856.json");
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        let mut stream = super::stream_items(file);
+        let mut count = 0;
+
+        for (streamed, item) in (&mut stream).zip(&items.values) {
+            assert_eq!(&streamed.unwrap(), item);
+            count += 1;
+        }
+
+        assert_eq!(count, 37647);
+        assert!(stream.next().is_none());
+        assert_eq!(stream.resume_key(), None);
+    }
+
+    #[test]
+    fn stream_items_resume_key() {
+        let contents = include_str!("../../../../examples/cdx/1740396642000000.json");
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        let mut stream = super::stream_items(contents.as_bytes());
+        let streamed = (&mut stream).collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(streamed, items.values);
+        assert_eq!(stream.resume_key(), items.resume_key.as_deref());
+        assert!(stream.resume_key().is_some());
+
+        assert_eq!(super::stream_items(" [ ] ".as_bytes()).count(), 0);
+
+        let mut invalid = super::stream_items("[[\"urlkey\"]]".as_bytes());
+        assert!(matches!(invalid.next(), Some(Err(super::Error::Json(_)))));
+        assert!(invalid.next().is_none());
+
+        let truncated = &contents[..contents.len() / 2];
+        assert!(
+            super::stream_items(truncated.as_bytes())
+                .collect::<Result<Vec<_>, _>>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn round_trip_serialize() {
+        for contents in [
+            include_str!("../../../../examples/cdx/1706619334645856.json"),
+            include_str!("../../../../examples/cdx/1740396642000000.json"),
+        ] {
+            let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+            let serialized = serde_json::to_string(&items).unwrap();
+            let parsed = serde_json::from_str::<super::ItemList>(&serialized).unwrap();
+
+            assert_eq!(parsed.values, items.values);
+            assert_eq!(parsed.resume_key, items.resume_key);
+        }
+
+        let empty = super::ItemList {
+            values: vec![],
+            resume_key: None,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&empty).unwrap(),
+            r#"[["urlkey","timestamp","original","mimetype","statuscode","digest","length"]]"#
+        );
+    }
+
+    #[test]
+    fn filter() {
+        let contents = include_str!("../../../../examples/cdx/1706619334645856.json");
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        let json = items
+            .filter()
+            .mime_type(super::MimeType::ApplicationJson)
+            .collect();
+
+        assert_eq!(json.len(), 33740);
+
+        let json_ok = items
+            .filter()
+            .mime_type(super::MimeType::ApplicationJson)
+            .status_code(super::StatusCode::Ok);
+
+        assert_eq!(json_ok.iter().count(), 18653);
+
+        let json_ok_2020 =
+            json_ok.timestamp_range("20200101000000..20210101000000".parse().unwrap());
+
+        assert_eq!(json_ok_2020.iter().count(), 2207);
+
+        let json_large = items
+            .filter()
+            .mime_type(super::MimeType::ApplicationJson)
+            .min_length(1000);
+
+        assert_eq!(json_large.iter().count(), 18269);
+        assert_eq!(items.filter().collect(), items.values);
+    }
+
+    #[test]
+    fn dedup_by_digest() {
+        let contents = r#"[["urlkey","timestamp","original","mimetype","statuscode","digest","length"],
+["com,example)/a","20200103000000","https://example.com/a","text/html","200","ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4","100"],
+["com,example)/b","20200102000000","https://example.com/b","text/html","200","VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5","100"],
+["com,example)/a","20200101000000","https://example.com/a","text/html","200","ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4","100"],
+["com,example)/c","20200104000000","https://example.com/c","text/html","200","INVALID","100"],
+["com,example)/a","20200102000000","https://example.com/a","text/html","200","ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4","100"],
+["com,example)/c","20200105000000","https://example.com/c","text/html","200","INVALID","100"],
+["com,example)/b","20200101000000","https://example.com/b","text/html","200","VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5","100"]]"#;
+
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+        let deduped = items.dedup_by_digest();
+
+        let timestamps = deduped
+            .iter()
+            .map(|item| (item.original.as_ref(), item.timestamp.to_string()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            timestamps,
+            vec![
+                ("https://example.com/a", "20200101000000".to_string()),
+                ("https://example.com/c", "20200104000000".to_string()),
+                ("https://example.com/c", "20200105000000".to_string()),
+                ("https://example.com/b", "20200101000000".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_text() {
+        let contents = "com,twitter)/jack/status/20 20060321205050 https://twitter.com/jack/status/20 text/html 200 ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4 1234
+com,twitter)/jack/status/20 20060321205051 https://twitter.com/jack/status/20 text/html - ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4 -
+
+eJwNxzEOgCAMAMCvuJqYtKViy3MIdGAgGqj6fb3tytk3f5u7jRVKvrw9VoflbkNgevZ7Amkilj0xBoqKCVWWgCH
+";
+
+        let items = super::ItemList::from_text(contents.as_bytes()).unwrap();
+
+        assert_eq!(items.values.len(), 2);
+        assert_eq!(items.values[0].timestamp.to_string(), "20060321205050");
+        assert_eq!(items.values[0].length, Some(1234));
+        assert_eq!(
+            items.values[1].status_code,
+            crate::cdx::status_code::StatusCode::Empty
+        );
+        assert_eq!(items.values[1].length, None);
+        assert_eq!(
+            items.resume_key.as_deref(),
+            Some(
+                "eJwNxzEOgCAMAMCvuJqYtKViy3MIdGAgGqj6fb3tytk3f5u7jRVKvrw9VoflbkNgevZ7Amkilj0xBoqKCVWWgCH"
+            )
+        );
+
+        let extended = "com,twitter)/jack/status/20 20060321205050 https://twitter.com/jack/status/20 text/html 200 ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4 - - 1234 5678 example.warc.gz\n";
+        let extended_items = super::ItemList::from_text(extended.as_bytes()).unwrap();
+
+        assert_eq!(extended_items.values, items.values[0..1]);
+        assert_eq!(extended_items.resume_key, None);
+
+        let invalid =
+            "com,twitter)/jack/status/20 20060321205050 https://twitter.com/jack/status/20\n";
+
+        assert!(matches!(
+            super::ItemList::from_text(invalid.as_bytes()),
+            Err(super::Error::InvalidTextLine(_))
+        ));
+
+        let content_after_resume_key = format!("{contents}{extended}");
+
+        assert!(super::ItemList::from_text(content_after_resume_key.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn merge_sorted() {
+        let contents = include_str!("../../../../examples/cdx/1740396642000000.json");
+        let mut items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        items.values.sort();
+        items.values.dedup();
+
+        let left = super::ItemList {
+            values: items.values[0..60].to_vec(),
+            resume_key: None,
+        };
+        let right = super::ItemList {
+            values: items.values[40..].to_vec(),
+            resume_key: items.resume_key.clone(),
+        };
+        let empty = super::ItemList {
+            values: vec![],
+            resume_key: None,
+        };
+
+        let merged = super::merge_sorted(vec![right, empty, left]);
+
+        assert_eq!(merged, items.values);
+        assert!(super::merge_sorted(vec![]).is_empty());
+    }
+
+    #[test]
+    fn next_page_query() {
+        let contents = include_str!("../../../../examples/cdx/1740396642000000.json");
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        let base_query = "https://web.archive.org/cdx/search/cdx?url=twitter.com/jack&output=json&showResumeKey=true";
+
+        assert_eq!(
+            items.next_page_query(base_query),
+            Some(format!(
+                "{base_query}&resumeKey=eJwNxzEOgCAMAMCvuJqYtKViy3MIdGAgGqj6fb3tytk3f5u7jRVKvrw9VoflbkNgevZ7Amkilj0xBoqKCVWWgCH-FTyYWD9RQxSp"
+            ))
+        );
+
+        let unsafe_key = super::ItemList {
+            values: vec![],
+            resume_key: Some("a+b/c=".into()),
+        };
+
+        assert_eq!(
+            unsafe_key.next_page_query("url=example.com"),
+            Some("url=example.com&resumeKey=a%2Bb%2Fc%3D".to_string())
+        );
+        assert_eq!(
+            unsafe_key.next_page_query(""),
+            Some("resumeKey=a%2Bb%2Fc%3D".to_string())
+        );
+
+        let complete = super::ItemList {
+            values: items.values,
+            resume_key: None,
+        };
+
+        assert_eq!(complete.next_page_query(base_query), None);
+    }
+
+    #[test]
+    fn from_reader() -> Result<(), Box<dyn std::error::Error>> {
+        let contents = include_str!("../../../../examples/cdx/1740396642000000.json");
+
+        let mut gzip_encoder =
+            flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        std::io::Write::write_all(&mut gzip_encoder, contents.as_bytes())?;
+        let gzip_contents = gzip_encoder.finish()?;
+
+        let plain = super::ItemList::from_reader(contents.as_bytes())?;
+        let gzip = super::ItemList::from_reader(gzip_contents.as_slice())?;
+
+        assert_eq!(plain.values.len(), 100);
+        assert_eq!(plain.values, gzip.values);
+        assert_eq!(plain.resume_key, gzip.resume_key);
+
+        let dir = tempfile::tempdir()?;
+        let gzip_path = dir.path().join("items.json.gz");
+        std::fs::write(&gzip_path, &gzip_contents)?;
+
+        assert_eq!(super::ItemList::from_path(&gzip_path)?.values, plain.values);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_json_with_max_items() {
+        let contents = include_str!("../../../../examples/cdx/1740396642000000.json");
+
+        let items = super::ItemList::parse_json_with_max_items(contents, 100).unwrap();
+
+        assert_eq!(items.values.len(), 100);
+        assert!(items.resume_key.is_some());
+
+        let result = super::ItemList::parse_json_with_max_items(contents, 10);
+
+        assert!(matches!(result, Err(super::Error::TooManyItems(10))));
+
+        let result = super::ItemList::parse_json_with_max_items("[[\"urlkey\"]]", 10);
+
+        assert!(matches!(result, Err(super::Error::Json(_))));
+    }
+
+    #[test]
+    fn deserialize_numeric_fields() {
+        let contents = r#"[["urlkey","timestamp","original","mimetype","statuscode","digest","length"],
+["com,twitter)/jack/status/20","20060321205050","https://twitter.com/jack/status/20","text/html",200,"ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4",1234],
+["com,twitter)/jack/status/20","20060321205051","https://twitter.com/jack/status/20","text/html","-","ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4","-"]]"#;
+
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        assert_eq!(items.values.len(), 2);
+        assert_eq!(
+            items.values[0].status_code,
+            crate::cdx::status_code::StatusCode::Ok
+        );
+        assert_eq!(items.values[0].length, Some(1234));
+        assert_eq!(
+            items.values[1].status_code,
+            crate::cdx::status_code::StatusCode::Empty
+        );
+        assert_eq!(items.values[1].length, None);
+
+        let other = contents.replace(",200,", ",299,");
+        let items = serde_json::from_str::<super::ItemList>(&other).unwrap();
+
+        assert_eq!(
+            items.values[0].status_code,
+            crate::cdx::status_code::StatusCode::Other(299)
+        );
+
+        let invalid = contents.replace(",200,", ",2000,");
+
+        assert!(serde_json::from_str::<super::ItemList>(&invalid).is_err());
+    }
+
     #[test]
     fn deserialize_with_resume_key() {
         let contents = include_str!("../../../../examples/cdx/1740396642000000.json");
@@ -237,4 +1235,94 @@ mod tests {
         assert_eq!(items.values.len(), 100);
         assert_eq!(items.resume_key, Some(expected_resume_key.into()));
     }
+
+    #[test]
+    fn serialize_fields() {
+        let contents = include_str!("../../../../examples/cdx/1740396642000000.json");
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        let fields = [
+            super::CdxField::UrlKey,
+            super::CdxField::Timestamp,
+            super::CdxField::Digest,
+        ];
+
+        let mut output = vec![];
+        items.serialize_fields(&fields, &mut output).unwrap();
+
+        let rows = serde_json::from_slice::<Vec<Vec<String>>>(&output).unwrap();
+
+        // The header, the items, an empty row, and the resume key.
+        assert_eq!(rows.len(), items.values.len() + 3);
+        assert_eq!(rows[0], vec!["urlkey", "timestamp", "digest"]);
+
+        for (row, item) in rows[1..].iter().zip(&items.values) {
+            assert_eq!(
+                row,
+                &vec![
+                    item.key.to_string(),
+                    item.timestamp.to_string(),
+                    item.digest.to_string()
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn serialize_all_fields_round_trip() {
+        let contents = include_str!("../../../../examples/cdx/1740396642000000.json");
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        let mut output = vec![];
+        items
+            .serialize_fields(&crate::cdx::field::CDX_FIELD_VALUES, &mut output)
+            .unwrap();
+
+        let parsed = serde_json::from_slice::<super::ItemList>(&output).unwrap();
+
+        assert_eq!(parsed.values, items.values);
+        assert_eq!(parsed.resume_key, items.resume_key);
+    }
+
+    #[test]
+    fn verify_length() {
+        let contents = r#"[
+            ["urlkey","timestamp","original","mimetype","statuscode","digest","length"],
+            ["com,disqus)/api/3.0/users/details.json","20131201022630","http://disqus.com:80/api/3.0/users/details.json?","application/json","403","DH4ICIMJH35MRVTO4YRM653PQRXH4VUZ","3700"],
+            ["com,disqus)/api/3.0/users/details.json","20150804175400","http://disqus.com:80/api/3.0/users/details.json?","application/json","403","DH4ICIMJH35MRVTO4YRM653PQRXH4VUZ","-"]
+        ]"#;
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        assert!(items.values[0].verify_length(3700));
+        assert!(items.values[0].verify_length(3700 - super::LENGTH_TOLERANCE));
+        assert!(!items.values[0].verify_length(3700 + super::LENGTH_TOLERANCE + 1));
+        assert!(!items.values[0].verify_length(0));
+        assert!(items.values[1].verify_length(0));
+    }
+
+    #[test]
+    fn captures_by_url() {
+        let contents = include_str!("../../../../examples/cdx/1706619334645856.json");
+        let items = serde_json::from_str::<super::ItemList>(contents).unwrap();
+
+        let captures = items.captures_by_url();
+
+        let expected = [
+            "20131201022630",
+            "20150804175400",
+            "20160702122839",
+            "20160803084856",
+            "20160904105942",
+            "20160905142405",
+        ]
+        .iter()
+        .map(|timestamp| timestamp.parse().unwrap())
+        .collect::<Vec<_>>();
+
+        assert_eq!(captures.len(), 20376);
+        assert_eq!(
+            captures.get("http://disqus.com:80/api/3.0/users/details.json?"),
+            Some(&expected)
+        );
+    }
 }
@@ -9,11 +9,17 @@ pub enum Error {
     Invalid(String),
 }
 
+/// A MIME type from a CDX result.
+///
+/// Values with parameters (e.g. `text/html; charset=utf-8`) are stored in their original form as
+/// `WithParams`, so use `base` to check the type independently of its parameters.
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum MimeType<'a> {
     TextHtml,
     ApplicationJson,
     Other(Cow<'a, str>),
+    // The complete original value (always contains a semicolon).
+    WithParams(Cow<'a, str>),
 }
 
 impl<'a> MimeType<'a> {
@@ -22,23 +28,67 @@ impl<'a> MimeType<'a> {
             Self::TextHtml => "text/html",
             Self::ApplicationJson => "application/json",
             Self::Other(value) => value,
+            Self::WithParams(value) => value,
         }
     }
 
     // TODO: Add validation here.
     pub fn parse_str(input: &'a str) -> Result<Self, Error> {
+        if input.contains(';') {
+            Ok(Self::WithParams(input.into()))
+        } else {
+            Ok(Self::parse_base(input))
+        }
+    }
+
+    fn parse_base(input: &'a str) -> Self {
         match input {
-            "text/html" => Ok(Self::TextHtml),
-            "application/json" => Ok(Self::ApplicationJson),
-            other => Ok(Self::Other(other.into())),
+            "text/html" => Self::TextHtml,
+            "application/json" => Self::ApplicationJson,
+            other => Self::Other(other.into()),
+        }
+    }
+
+    /// The MIME type without any parameters.
+    pub fn base(&self) -> MimeType<'_> {
+        match self {
+            Self::TextHtml => MimeType::TextHtml,
+            Self::ApplicationJson => MimeType::ApplicationJson,
+            Self::Other(value) => MimeType::Other(value.as_ref().into()),
+            Self::WithParams(value) => MimeType::parse_base(
+                value
+                    .split_once(';')
+                    .map_or(value.as_ref(), |(base, _)| base)
+                    .trim(),
+            ),
+        }
+    }
+
+    /// The parameters following the base type, if any (e.g. `charset=utf-8`).
+    pub fn params(&self) -> Option<&str> {
+        match self {
+            Self::WithParams(value) => value.split_once(';').map(|(_, params)| params.trim()),
+            _ => None,
         }
     }
 
+    /// The value of the `charset` parameter, if present.
+    pub fn charset(&self) -> Option<&str> {
+        self.params()?.split(';').find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+
+            key.trim()
+                .eq_ignore_ascii_case("charset")
+                .then(|| value.trim().trim_matches('"'))
+        })
+    }
+
     pub fn into_owned(self) -> MimeType<'static> {
         match self {
             Self::TextHtml => MimeType::TextHtml,
             Self::ApplicationJson => MimeType::ApplicationJson,
             Self::Other(other) => MimeType::Other(other.into_owned().into()),
+            Self::WithParams(value) => MimeType::WithParams(value.into_owned().into()),
         }
     }
 }
@@ -84,3 +134,35 @@ impl<'a, 'de: 'a> Deserialize<'de> for MimeType<'a> {
         deserializer.deserialize_str(MimeTypeVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MimeType;
+
+    #[test]
+    fn params() {
+        let input = "text/html; charset=utf-8";
+        let mime_type = MimeType::parse_str(input).unwrap();
+
+        assert_eq!(mime_type.base(), MimeType::TextHtml);
+        assert_eq!(mime_type.params(), Some("charset=utf-8"));
+        assert_eq!(mime_type.charset(), Some("utf-8"));
+        assert_eq!(mime_type.as_str(), input);
+        assert_eq!(mime_type.to_string(), input);
+
+        let quoted = MimeType::parse_str("application/json;Charset=\"UTF-8\";q=1").unwrap();
+
+        assert_eq!(quoted.base(), MimeType::ApplicationJson);
+        assert_eq!(quoted.charset(), Some("UTF-8"));
+
+        let other = MimeType::parse_str("warc/revisit").unwrap();
+
+        assert_eq!(other.base(), other);
+        assert_eq!(other.params(), None);
+        assert_eq!(other.charset(), None);
+        assert_eq!(
+            MimeType::parse_str("text/html").unwrap(),
+            MimeType::TextHtml
+        );
+    }
+}
@@ -1,3 +1,63 @@
+use std::path::Path;
+
+#[cfg(feature = "client")]
+pub mod client;
+pub mod field;
 pub mod item;
 pub mod mime_type;
+pub mod query;
+pub mod stats;
 pub mod status_code;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Read a CDX item list from a file that may be gzip or zstd compressed.
+///
+/// The compression type is detected from the first bytes of the file.
+pub fn read_item_list<P: AsRef<Path>>(path: P) -> Result<item::ItemList<'static>, item::Error> {
+    item::ItemList::from_path(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    #[test]
+    fn read_item_list_compressed() -> Result<(), Box<dyn std::error::Error>> {
+        let contents = include_str!("../../../examples/cdx/1706619334645856.json");
+        let dir = tempfile::tempdir()?;
+
+        let plain_path = dir.path().join("plain.json");
+        std::fs::write(&plain_path, contents)?;
+
+        let gzip_path = dir.path().join("gzip.json.gz");
+        let mut gzip_encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&gzip_path)?,
+            flate2::Compression::default(),
+        );
+        gzip_encoder.write_all(contents.as_bytes())?;
+        gzip_encoder.finish()?;
+
+        let zstd_path = dir.path().join("zstd.json.zst");
+        std::fs::write(&zstd_path, zstd::encode_all(contents.as_bytes(), 0)?)?;
+
+        let plain = super::read_item_list(&plain_path)?;
+        let gzip = super::read_item_list(&gzip_path)?;
+        let zstd = super::read_item_list(&zstd_path)?;
+
+        assert_eq!(plain.values.len(), 37647);
+        assert_eq!(plain.values, gzip.values);
+        assert_eq!(plain.values, zstd.values);
+
+        let (plain_match_count, plain_mismatches) = plain.check_json_surts()?;
+
+        assert!(plain_match_count > 0);
+        assert_eq!(
+            gzip.check_json_surts()?,
+            (plain_match_count, plain_mismatches)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+//! Construction of Wayback Machine CDX API query URLs.
+
+use crate::cdx::field::CdxField;
+use crate::surt::Surt;
+use crate::timestamp::Timestamp;
+
+pub const CDX_SEARCH_URL: &str = "https://web.archive.org/cdx/search/cdx";
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum MatchType {
+    #[default]
+    Exact,
+    Prefix,
+    Host,
+    Domain,
+}
+
+impl MatchType {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::Prefix => "prefix",
+            Self::Host => "host",
+            Self::Domain => "domain",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CdxQuery {
+    url: String,
+    match_type: MatchType,
+    from: Option<Timestamp>,
+    to: Option<Timestamp>,
+    filters: Vec<(CdxField, String)>,
+    limit: Option<u32>,
+    show_resume_key: bool,
+    resume_key: Option<String>,
+    output_json: bool,
+}
+
+impl Default for CdxQuery {
+    /// An exact-match query for JSON output.
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            match_type: MatchType::default(),
+            from: None,
+            to: None,
+            filters: vec![],
+            limit: None,
+            show_resume_key: false,
+            resume_key: None,
+            output_json: true,
+        }
+    }
+}
+
+impl CdxQuery {
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+
+    pub fn match_type(mut self, match_type: MatchType) -> Self {
+        self.match_type = match_type;
+        self
+    }
+
+    pub fn from(mut self, from: Timestamp) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn to(mut self, to: Timestamp) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Add a filter requiring the field to match the regular expression.
+    pub fn filter(mut self, field: CdxField, regex: &str) -> Self {
+        self.filters.push((field, regex.to_string()));
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Request a resume key for the next page of results, which is only provided for limited queries.
+    pub fn show_resume_key(mut self, show_resume_key: bool) -> Self {
+        self.show_resume_key = show_resume_key;
+        self
+    }
+
+    pub fn resume_key(mut self, resume_key: &str) -> Self {
+        self.resume_key = Some(resume_key.to_string());
+        self
+    }
+
+    pub fn output_json(mut self, output_json: bool) -> Self {
+        self.output_json = output_json;
+        self
+    }
+
+    pub fn is_output_json(&self) -> bool {
+        self.output_json
+    }
+
+    /// The URL as it will be sent.
+    ///
+    /// For host and domain queries, the host is extracted and normalized (for example by removing a `www` prefix) in
+    /// the same way as for SURTs. Other URLs are used as given, since the CDX server normalizes them itself, and if
+    /// the host can't be determined the URL is also used as given.
+    pub fn normalized_url(&self) -> String {
+        match self.match_type {
+            MatchType::Host | MatchType::Domain => {
+                let url = if self.url.contains("://") {
+                    self.url.clone()
+                } else {
+                    format!("http://{}", self.url)
+                };
+
+                Surt::from_url(&url).map_or_else(
+                    |_| self.url.clone(),
+                    |surt| {
+                        let host = surt.domain_name_parts().rev().collect::<Vec<_>>().join(".");
+
+                        match surt.port() {
+                            Some(port) => format!("{host}:{port}"),
+                            None => host,
+                        }
+                    },
+                )
+            }
+            MatchType::Exact | MatchType::Prefix => self.url.clone(),
+        }
+    }
+
+    /// The percent-encoded query string (without a leading `?`).
+    pub fn query_string(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+
+        serializer.append_pair("url", &self.normalized_url());
+
+        if self.match_type != MatchType::Exact {
+            serializer.append_pair("matchType", self.match_type.as_str());
+        }
+
+        if let Some(from) = self.from {
+            serializer.append_pair("from", &from.to_seconds_string());
+        }
+
+        if let Some(to) = self.to {
+            serializer.append_pair("to", &to.to_seconds_string());
+        }
+
+        for (field, regex) in &self.filters {
+            serializer.append_pair("filter", &format!("{field}:{regex}"));
+        }
+
+        if let Some(limit) = self.limit {
+            serializer.append_pair("limit", &limit.to_string());
+        }
+
+        if self.show_resume_key {
+            serializer.append_pair("showResumeKey", "true");
+        }
+
+        if let Some(resume_key) = &self.resume_key {
+            serializer.append_pair("resumeKey", resume_key);
+        }
+
+        if self.output_json {
+            serializer.append_pair("output", "json");
+        }
+
+        serializer.finish()
+    }
+
+    pub fn build(&self) -> String {
+        format!("{CDX_SEARCH_URL}?{}", self.query_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_exact() {
+        let query = CdxQuery::default().url("https://twitter.com/jack/status/20");
+
+        assert_eq!(
+            query.build(),
+            "https://web.archive.org/cdx/search/cdx?url=https%3A%2F%2Ftwitter.com%2Fjack%2Fstatus%2F20&output=json"
+        );
+    }
+
+    #[test]
+    fn build_domain() {
+        let query = CdxQuery::default()
+            .url("https://www.Twitter.com/jack")
+            .match_type(MatchType::Domain)
+            .from("20060321000000".parse().unwrap())
+            .to("20070101000000999".parse().unwrap())
+            .filter(CdxField::StatusCode, "2..")
+            .filter(CdxField::MimeType, "text/html")
+            .limit(1000)
+            .show_resume_key(true)
+            .resume_key("com,twitter)/jack 20060321205050");
+
+        assert_eq!(query.normalized_url(), "twitter.com");
+        assert_eq!(
+            query.query_string(),
+            "url=twitter.com&matchType=domain&from=20060321000000&to=20070101000000&filter=statuscode%3A2..&filter=mimetype%3Atext%2Fhtml&limit=1000&showResumeKey=true&resumeKey=com%2Ctwitter%29%2Fjack+20060321205050&output=json"
+        );
+    }
+
+    #[test]
+    fn normalized_host() {
+        let query = CdxQuery::default()
+            .url("www.example.com:8080")
+            .match_type(MatchType::Host);
+
+        assert_eq!(query.normalized_url(), "example.com:8080");
+    }
+}
@@ -0,0 +1,40 @@
+//! Statistics about capture coverage.
+
+use crate::timestamp::Timestamp;
+use chrono::{DateTime, Duration, Utc};
+
+/// Compute the intervals between consecutive captures.
+///
+/// The input should be sorted in ascending order (as in the values returned by `ItemList::captures_by_url`).
+pub fn capture_gaps(timestamps: &[Timestamp]) -> Vec<Duration> {
+    timestamps
+        .windows(2)
+        .map(|pair| DateTime::<Utc>::from(pair[1]) - DateTime::<Utc>::from(pair[0]))
+        .collect()
+}
+
+/// The longest interval between consecutive captures (if there are at least two captures).
+pub fn longest_gap(timestamps: &[Timestamp]) -> Option<Duration> {
+    capture_gaps(timestamps).into_iter().max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_gaps_and_longest_gap() {
+        let timestamps = ["20200101000000", "20200102000000", "20200201000000"]
+            .iter()
+            .map(|timestamp| timestamp.parse::<Timestamp>().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            capture_gaps(&timestamps),
+            vec![Duration::days(1), Duration::days(30)]
+        );
+        assert_eq!(longest_gap(&timestamps), Some(Duration::days(30)));
+        assert_eq!(longest_gap(&timestamps[0..1]), None);
+        assert_eq!(longest_gap(&[]), None);
+    }
+}
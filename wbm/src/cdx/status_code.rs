@@ -1,25 +1,34 @@
-use std::{fmt::Display, str::FromStr};
+use serde::de::{Deserialize, Deserializer, Unexpected, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::{borrow::Cow, fmt::Display, str::FromStr};
 
-pub const STATUS_CODE_VALUES: [StatusCode; 20] = [
+pub const STATUS_CODE_VALUES: [StatusCode; 27] = [
     StatusCode::Empty,
     StatusCode::Ok,
+    StatusCode::NoContent,
+    StatusCode::PartialContent,
     StatusCode::MovedPermanently,
     StatusCode::Found,
     StatusCode::SeeOther,
     StatusCode::TemporaryRedirest,
+    StatusCode::PermanentRedirect,
     StatusCode::BadRequest,
     StatusCode::Unauthorized,
     StatusCode::Forbidden,
     StatusCode::NotFound,
+    StatusCode::Gone,
     StatusCode::UpgradeRequired,
     StatusCode::TooManyRequests,
     StatusCode::RequestHeaderFieldsTooLarge,
+    StatusCode::UnavailableForLegalReasons,
     StatusCode::InternalServerError,
     StatusCode::BadGateway,
     StatusCode::ServiceUnavailable,
     StatusCode::GatewayTimeout,
     StatusCode::CloudflareUnknownError,
     StatusCode::CloudflareWebServerDown,
+    StatusCode::CloudflareConnectionTimedOut,
+    StatusCode::CloudflareOriginUnreachable,
     StatusCode::CloudflareTimeout,
 ];
 
@@ -31,56 +40,45 @@ pub enum Error {
 
 /// An HTTP status code.
 ///
-/// This is a simplified representation that only provides coverage for values relevant to our CDX
-/// index results. The serialization encoding provided here is the one seen in these results.
-#[derive(
-    Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize,
-)]
+/// This is a simplified representation that only provides named variants for values relevant to our
+/// CDX index results. Other valid (three-digit) codes are represented by `Other`, which is ordered
+/// after all named variants. The serialization encoding provided here is the one seen in these
+/// results (e.g. `"200"` or `"-"`).
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum StatusCode {
     // Represents a hyphen in the CDX result, which typically indicates a `200` response.
-    #[serde(alias = "-")]
     Empty,
-    #[serde(alias = "200")]
     Ok,
+    NoContent,
+    PartialContent,
     // Temporary redirect.
-    #[serde(alias = "301")]
     MovedPermanently,
     // Temporary redirect.
-    #[serde(alias = "302")]
     Found,
-    #[serde(alias = "303")]
     SeeOther,
-    #[serde(alias = "307")]
     TemporaryRedirest,
-    #[serde(alias = "400")]
+    PermanentRedirect,
     BadRequest,
-    #[serde(alias = "401")]
     Unauthorized,
-    #[serde(alias = "403")]
     Forbidden,
-    #[serde(alias = "404")]
     NotFound,
-    #[serde(alias = "426")]
+    Gone,
     UpgradeRequired,
     // Temporary redirect.
-    #[serde(alias = "429")]
     TooManyRequests,
-    #[serde(alias = "431")]
     RequestHeaderFieldsTooLarge,
-    #[serde(alias = "500")]
+    UnavailableForLegalReasons,
     InternalServerError,
-    #[serde(alias = "502")]
     BadGateway,
-    #[serde(alias = "503")]
     ServiceUnavailable,
-    #[serde(alias = "504")]
     GatewayTimeout,
-    #[serde(alias = "520")]
     CloudflareUnknownError,
-    #[serde(alias = "521")]
     CloudflareWebServerDown,
-    #[serde(alias = "524")]
+    CloudflareConnectionTimedOut,
+    CloudflareOriginUnreachable,
     CloudflareTimeout,
+    // Any other valid status code (never one of the values above).
+    Other(u16),
 }
 
 impl StatusCode {
@@ -93,82 +91,124 @@ impl StatusCode {
         match self {
             Self::Empty => 0,
             Self::Ok => 200,
+            Self::NoContent => 204,
+            Self::PartialContent => 206,
             Self::MovedPermanently => 301,
             Self::Found => 302,
             Self::SeeOther => 303,
             Self::TemporaryRedirest => 307,
+            Self::PermanentRedirect => 308,
             Self::BadRequest => 400,
             Self::Unauthorized => 401,
             Self::Forbidden => 403,
             Self::NotFound => 404,
+            Self::Gone => 410,
             Self::UpgradeRequired => 426,
             Self::TooManyRequests => 429,
             Self::RequestHeaderFieldsTooLarge => 431,
+            Self::UnavailableForLegalReasons => 451,
             Self::InternalServerError => 500,
             Self::BadGateway => 502,
             Self::ServiceUnavailable => 503,
             Self::GatewayTimeout => 504,
             Self::CloudflareUnknownError => 520,
             Self::CloudflareWebServerDown => 521,
+            Self::CloudflareConnectionTimedOut => 522,
+            Self::CloudflareOriginUnreachable => 523,
             Self::CloudflareTimeout => 524,
+            Self::Other(value) => *value,
         }
     }
 
+    /// Indicates a redirect that the Wayback Machine will follow (`301`, `302`, `303`, `307`, or `308`).
+    pub const fn is_redirect(&self) -> bool {
+        matches!(self.value(), 301 | 302 | 303 | 307 | 308)
+    }
+
+    /// Indicates a `2xx` status code, where an empty value is treated as a `200`.
+    pub const fn is_success(&self) -> bool {
+        matches!(self, Self::Empty) || matches!(self.value(), 200..=299)
+    }
+
+    pub const fn is_client_error(&self) -> bool {
+        matches!(self.value(), 400..=499)
+    }
+
+    pub const fn is_server_error(&self) -> bool {
+        matches!(self.value(), 500..=599)
+    }
+
     pub const fn from_value(value: u16) -> Result<Self, Error> {
         match value {
             0 => Ok(Self::Empty),
             200 => Ok(Self::Ok),
+            204 => Ok(Self::NoContent),
+            206 => Ok(Self::PartialContent),
             301 => Ok(Self::MovedPermanently),
             302 => Ok(Self::Found),
             303 => Ok(Self::SeeOther),
             307 => Ok(Self::TemporaryRedirest),
+            308 => Ok(Self::PermanentRedirect),
             400 => Ok(Self::BadRequest),
             401 => Ok(Self::Unauthorized),
             403 => Ok(Self::Forbidden),
             404 => Ok(Self::NotFound),
+            410 => Ok(Self::Gone),
             426 => Ok(Self::UpgradeRequired),
             429 => Ok(Self::TooManyRequests),
             431 => Ok(Self::RequestHeaderFieldsTooLarge),
+            451 => Ok(Self::UnavailableForLegalReasons),
             500 => Ok(Self::InternalServerError),
             502 => Ok(Self::BadGateway),
             503 => Ok(Self::ServiceUnavailable),
             504 => Ok(Self::GatewayTimeout),
             520 => Ok(Self::CloudflareUnknownError),
             521 => Ok(Self::CloudflareWebServerDown),
+            522 => Ok(Self::CloudflareConnectionTimedOut),
+            523 => Ok(Self::CloudflareOriginUnreachable),
             524 => Ok(Self::CloudflareTimeout),
+            100..=999 => Ok(Self::Other(value)),
             _ => Err(Error::Unsupported),
         }
     }
 
-    pub const fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> Cow<'static, str> {
         match self {
-            Self::Empty => "-",
-            Self::Ok => "200",
-            Self::MovedPermanently => "301",
-            Self::Found => "302",
-            Self::SeeOther => "303",
-            Self::TemporaryRedirest => "307",
-            Self::BadRequest => "400",
-            Self::Unauthorized => "401",
-            Self::Forbidden => "403",
-            Self::NotFound => "404",
-            Self::UpgradeRequired => "426",
-            Self::TooManyRequests => "429",
-            Self::RequestHeaderFieldsTooLarge => "431",
-            Self::InternalServerError => "500",
-            Self::BadGateway => "502",
-            Self::ServiceUnavailable => "503",
-            Self::GatewayTimeout => "504",
-            Self::CloudflareUnknownError => "520",
-            Self::CloudflareWebServerDown => "521",
-            Self::CloudflareTimeout => "524",
+            Self::Empty => "-".into(),
+            Self::Ok => "200".into(),
+            Self::NoContent => "204".into(),
+            Self::PartialContent => "206".into(),
+            Self::MovedPermanently => "301".into(),
+            Self::Found => "302".into(),
+            Self::SeeOther => "303".into(),
+            Self::TemporaryRedirest => "307".into(),
+            Self::PermanentRedirect => "308".into(),
+            Self::BadRequest => "400".into(),
+            Self::Unauthorized => "401".into(),
+            Self::Forbidden => "403".into(),
+            Self::NotFound => "404".into(),
+            Self::Gone => "410".into(),
+            Self::UpgradeRequired => "426".into(),
+            Self::TooManyRequests => "429".into(),
+            Self::RequestHeaderFieldsTooLarge => "431".into(),
+            Self::UnavailableForLegalReasons => "451".into(),
+            Self::InternalServerError => "500".into(),
+            Self::BadGateway => "502".into(),
+            Self::ServiceUnavailable => "503".into(),
+            Self::GatewayTimeout => "504".into(),
+            Self::CloudflareUnknownError => "520".into(),
+            Self::CloudflareWebServerDown => "521".into(),
+            Self::CloudflareConnectionTimedOut => "522".into(),
+            Self::CloudflareOriginUnreachable => "523".into(),
+            Self::CloudflareTimeout => "524".into(),
+            Self::Other(value) => value.to_string().into(),
         }
     }
 }
 
 impl Display for StatusCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.as_str())
+        f.write_str(&self.as_str())
     }
 }
 
@@ -176,58 +216,91 @@ impl FromStr for StatusCode {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "-" => Ok(Self::Empty),
-            "200" => Ok(Self::Ok),
-            "301" => Ok(Self::MovedPermanently),
-            "302" => Ok(Self::Found),
-            "303" => Ok(Self::SeeOther),
-            "307" => Ok(Self::TemporaryRedirest),
-            "400" => Ok(Self::BadRequest),
-            "401" => Ok(Self::Unauthorized),
-            "403" => Ok(Self::Forbidden),
-            "404" => Ok(Self::NotFound),
-            "426" => Ok(Self::UpgradeRequired),
-            "429" => Ok(Self::TooManyRequests),
-            "431" => Ok(Self::RequestHeaderFieldsTooLarge),
-            "500" => Ok(Self::InternalServerError),
-            "502" => Ok(Self::BadGateway),
-            "503" => Ok(Self::ServiceUnavailable),
-            "504" => Ok(Self::GatewayTimeout),
-            "520" => Ok(Self::CloudflareUnknownError),
-            "521" => Ok(Self::CloudflareWebServerDown),
-            "524" => Ok(Self::CloudflareTimeout),
-            _ => Err(Self::Err::Unsupported),
+        if s == "-" {
+            Ok(Self::Empty)
+        } else if s.len() == 3 && s.bytes().all(|byte| byte.is_ascii_digit()) {
+            // Safe because we've checked that the value has three digits.
+            let value = s.parse::<u16>().unwrap();
+
+            if value >= 100 {
+                Self::from_value(value)
+            } else {
+                Err(Self::Err::Unsupported)
+            }
+        } else {
+            Err(Self::Err::Unsupported)
         }
     }
 }
 
+impl<'de> Deserialize<'de> for StatusCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StatusCodeVisitor;
+
+        impl<'de> Visitor<'de> for StatusCodeVisitor {
+            type Value = StatusCode;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("enum StatusCode")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse()
+                    .map_err(|_| serde::de::Error::invalid_value(Unexpected::Str(v), &self))
+            }
+        }
+
+        deserializer.deserialize_str(StatusCodeVisitor)
+    }
+}
+
+impl Serialize for StatusCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.as_str())
+    }
+}
+
 impl From<StatusCode> for http::status::StatusCode {
     /// Note that the Cloudflare error status codes are converted to the generic `500`.
     fn from(value: StatusCode) -> Self {
         match value {
             StatusCode::Empty => http::status::StatusCode::OK,
             StatusCode::Ok => http::status::StatusCode::OK,
+            StatusCode::NoContent => http::status::StatusCode::NO_CONTENT,
+            StatusCode::PartialContent => http::status::StatusCode::PARTIAL_CONTENT,
             StatusCode::MovedPermanently => http::status::StatusCode::MOVED_PERMANENTLY,
             StatusCode::Found => http::status::StatusCode::FOUND,
             StatusCode::SeeOther => http::status::StatusCode::SEE_OTHER,
             StatusCode::TemporaryRedirest => http::status::StatusCode::TEMPORARY_REDIRECT,
+            StatusCode::PermanentRedirect => http::status::StatusCode::PERMANENT_REDIRECT,
             StatusCode::BadRequest => http::status::StatusCode::BAD_REQUEST,
             StatusCode::Unauthorized => http::status::StatusCode::UNAUTHORIZED,
             StatusCode::Forbidden => http::status::StatusCode::FORBIDDEN,
             StatusCode::NotFound => http::status::StatusCode::NOT_FOUND,
+            StatusCode::Gone => http::status::StatusCode::GONE,
             StatusCode::UpgradeRequired => http::status::StatusCode::UPGRADE_REQUIRED,
             StatusCode::TooManyRequests => http::status::StatusCode::TOO_MANY_REQUESTS,
             StatusCode::RequestHeaderFieldsTooLarge => {
                 http::status::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
             }
+            StatusCode::UnavailableForLegalReasons => {
+                http::status::StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS
+            }
             StatusCode::InternalServerError => http::status::StatusCode::INTERNAL_SERVER_ERROR,
             StatusCode::BadGateway => http::status::StatusCode::BAD_GATEWAY,
             StatusCode::ServiceUnavailable => http::status::StatusCode::SERVICE_UNAVAILABLE,
             StatusCode::GatewayTimeout => http::status::StatusCode::GATEWAY_TIMEOUT,
             StatusCode::CloudflareUnknownError => http::status::StatusCode::INTERNAL_SERVER_ERROR,
             StatusCode::CloudflareWebServerDown => http::status::StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::CloudflareConnectionTimedOut => {
+                http::status::StatusCode::INTERNAL_SERVER_ERROR
+            }
+            StatusCode::CloudflareOriginUnreachable => {
+                http::status::StatusCode::INTERNAL_SERVER_ERROR
+            }
             StatusCode::CloudflareTimeout => http::status::StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::Other(value) => http::status::StatusCode::from_u16(value)
+                .unwrap_or(http::status::StatusCode::INTERNAL_SERVER_ERROR),
         }
     }
 }
@@ -273,12 +346,92 @@ mod test {
     }
 
     #[test]
-    fn round_trip_json() {
+    fn classification() {
+        use super::StatusCode;
+
         for status_code in super::STATUS_CODE_VALUES {
+            let flags = [
+                status_code.is_redirect(),
+                status_code.is_success(),
+                status_code.is_client_error(),
+                status_code.is_server_error(),
+            ];
+
+            // Every known status code has exactly one classification.
+            assert_eq!(
+                flags.iter().filter(|flag| **flag).count(),
+                1,
+                "{status_code:?}"
+            );
+
+            let expected_success = status_code == StatusCode::Empty
+                || http::status::StatusCode::from(status_code).is_success();
+
+            assert_eq!(status_code.is_success(), expected_success);
+        }
+
+        assert!(StatusCode::Empty.is_success());
+        assert!(StatusCode::Ok.is_success());
+        assert!(StatusCode::NoContent.is_success());
+        assert!(StatusCode::PermanentRedirect.is_redirect());
+        assert!(StatusCode::MovedPermanently.is_redirect());
+        assert!(StatusCode::Gone.is_client_error());
+        assert!(StatusCode::CloudflareTimeout.is_server_error());
+        assert!(StatusCode::Other(299).is_success());
+        assert!(!StatusCode::Other(304).is_redirect());
+        assert!(StatusCode::Other(418).is_client_error());
+    }
+
+    #[test]
+    fn other() {
+        let parsed = "418".parse::<super::StatusCode>();
+
+        assert_eq!(parsed, Ok(super::StatusCode::Other(418)));
+        assert_eq!(super::StatusCode::Other(418).to_string(), "418");
+        assert_eq!(super::StatusCode::Other(418).value(), 418);
+        assert_eq!(
+            super::StatusCode::from_value(418),
+            Ok(super::StatusCode::Other(418))
+        );
+        assert_eq!(
+            http::status::StatusCode::from(super::StatusCode::Other(418)),
+            http::status::StatusCode::IM_A_TEAPOT
+        );
+
+        // Known values are never represented as `Other`.
+        assert_eq!(
+            "200".parse::<super::StatusCode>(),
+            Ok(super::StatusCode::Ok)
+        );
+        assert_eq!(
+            super::StatusCode::from_value(200),
+            Ok(super::StatusCode::Ok)
+        );
+
+        for invalid in ["", "0", "099", "1000", "+418", "abc"] {
+            assert_eq!(
+                invalid.parse::<super::StatusCode>(),
+                Err(super::Error::Unsupported)
+            );
+        }
+
+        assert_eq!(
+            super::StatusCode::from_value(1000),
+            Err(super::Error::Unsupported)
+        );
+    }
+
+    #[test]
+    fn round_trip_json() {
+        for status_code in super::STATUS_CODE_VALUES
+            .into_iter()
+            .chain(std::iter::once(super::StatusCode::Other(418)))
+        {
             let status_code_json = serde_json::json!(status_code);
             let parsed: super::StatusCode =
                 serde_json::from_str(&status_code_json.to_string()).unwrap();
 
+            assert_eq!(status_code_json, serde_json::json!(status_code.as_str()));
             assert_eq!(parsed, status_code);
         }
     }
@@ -3,13 +3,17 @@
 //! The Wayback Machine's CDX index provides a digest for each page in its
 //! search results. In most cases these are Base32-encoded SHA-1 digests,
 //! but some use unknown encodings.
+//!
+//! SHA-256 digests are also supported for stores that use them. These are
+//! Base32-encoded without padding (52 characters).
 
-use data_encoding::BASE32;
+use data_encoding::{BASE32, BASE32_NOPAD, HEXLOWER, HEXLOWER_PERMISSIVE};
 use serde::{
     de::{Deserialize, Deserializer, Unexpected, Visitor},
     ser::{Serialize, Serializer},
 };
 use sha1::Digest as _;
+use sha1::digest::{FixedOutputReset, Output};
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::io::{BufWriter, Read, Write};
@@ -28,6 +32,12 @@ pub enum Error {
     Invalid(String),
     #[error("Invalid SHA-1 digest length")]
     InvalidBytesLength(Vec<u8>),
+    #[error("Invalid SHA-256 digest string length")]
+    InvalidSha256Length(String),
+    #[error("Invalid SHA-256 digest string input")]
+    InvalidSha256(String),
+    #[error("Invalid SHA-256 digest length")]
+    InvalidSha256BytesLength(Vec<u8>),
     #[error("Decoding error")]
     Decoding(data_encoding::DecodePartial),
 }
@@ -42,15 +52,26 @@ impl Sha1Computer {
         Sha1Computer::default().digest(input)
     }
 
-    /// Compute the SHA-1 hash for bytes read from a source.
-    pub fn digest_bytes<R: Read>(&self, input: &mut R) -> std::io::Result<[u8; 20]> {
+    /// Add bytes read from a source to the current hash without finalizing it.
+    ///
+    /// Any bytes added in this way will be included in the next digest computed by this instance.
+    pub fn update<R: Read>(&self, input: &mut R) -> std::io::Result<()> {
         let mut writer = self.writer.lock().unwrap();
         std::io::copy(input, &mut writer.get_mut())?;
-        writer.flush()?;
 
-        let bytes = writer.get_mut().finalize_reset();
+        Ok(())
+    }
+
+    /// Finalize the current hash and reset the computer.
+    pub fn finish(&self) -> Sha1Digest {
+        let mut writer = self.writer.lock().unwrap();
+
+        Sha1Digest(sha1::Digest::finalize_reset(writer.get_mut()).into())
+    }
 
-        Ok(bytes.into())
+    /// Compute the SHA-1 hash for bytes read from a source.
+    pub fn digest_bytes<R: Read>(&self, input: &mut R) -> std::io::Result<[u8; 20]> {
+        Ok(digest_with(&self.writer, input)?.into())
     }
 
     /// Compute the SHA-1 hash for bytes read from a source.
@@ -80,6 +101,60 @@ impl Default for Sha1Computer {
     }
 }
 
+#[derive(Clone)]
+pub struct Sha256Computer {
+    writer: Arc<Mutex<BufWriter<sha2::Sha256>>>,
+}
+
+impl Sha256Computer {
+    pub fn compute_digest<R: Read>(input: &mut R) -> std::io::Result<Sha256Digest> {
+        Sha256Computer::default().digest(input)
+    }
+
+    /// Compute the SHA-256 hash for bytes read from a source.
+    pub fn digest_bytes<R: Read>(&self, input: &mut R) -> std::io::Result<[u8; 32]> {
+        Ok(digest_with(&self.writer, input)?.into())
+    }
+
+    /// Compute the SHA-256 hash for bytes read from a source.
+    pub fn digest<R: Read>(&self, input: &mut R) -> std::io::Result<Sha256Digest> {
+        let bytes = self.digest_bytes(input)?;
+
+        Ok(Sha256Digest(bytes))
+    }
+
+    /// Compute the SHA-256 hash for bytes read from a source and encode it as an
+    /// unpadded Base32 string.
+    pub fn digest_base32<R: Read>(&self, input: &mut R) -> std::io::Result<String> {
+        let bytes = self.digest_bytes(input)?;
+
+        let mut output = String::new();
+        BASE32_NOPAD.encode_append(&bytes, &mut output);
+
+        Ok(output)
+    }
+}
+
+impl Default for Sha256Computer {
+    fn default() -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(sha2::Sha256::new()))),
+        }
+    }
+}
+
+/// Stream bytes from a source through a shared hasher and finalize it, leaving it reset.
+fn digest_with<H: sha1::Digest + FixedOutputReset + Write, R: Read>(
+    writer: &Mutex<BufWriter<H>>,
+    input: &mut R,
+) -> std::io::Result<Output<H>> {
+    let mut writer = writer.lock().unwrap();
+    std::io::copy(input, &mut writer.get_mut())?;
+    writer.flush()?;
+
+    Ok(sha1::Digest::finalize_reset(writer.get_mut()))
+}
+
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Digest<'a> {
     Valid(Sha1Digest),
@@ -87,6 +162,15 @@ pub enum Digest<'a> {
 }
 
 impl<'a> Digest<'a> {
+    pub fn new_valid(digest: Sha1Digest) -> Self {
+        Self::Valid(digest)
+    }
+
+    /// Construct an invalid digest without checking whether the value is a valid SHA-1 digest.
+    pub fn new_invalid<S: Into<Cow<'a, str>>>(digest: S) -> Self {
+        Self::Invalid(digest.into())
+    }
+
     pub fn valid(&self) -> Option<Sha1Digest> {
         match self {
             Self::Valid(digest) => Some(*digest),
@@ -115,17 +199,14 @@ impl<'a> Digest<'a> {
         }
     }
 
+    /// Parse a Base32-encoded digest, preserving any value that can't be decoded as `Invalid`.
     pub fn parse_str(input: &'a str) -> Result<Self, Error> {
         if input.len() == 32 {
             let mut output = [0; 20];
-            let count = BASE32
-                .decode_mut(input.as_bytes(), &mut output)
-                .map_err(Error::Decoding)?;
 
-            if count == 20 {
-                Ok(Self::Valid(Sha1Digest(output)))
-            } else {
-                Ok(Self::Invalid(input.into()))
+            match BASE32.decode_mut(input.as_bytes(), &mut output) {
+                Ok(20) => Ok(Self::Valid(Sha1Digest(output))),
+                _ => Ok(Self::Invalid(input.into())),
             }
         } else {
             Ok(Self::Invalid(input.into()))
@@ -196,6 +277,39 @@ pub struct Sha1Digest(pub [u8; 20]);
 impl Sha1Digest {
     pub const MIN: Self = Self([u8::MIN; 20]);
     pub const MAX: Self = Self([u8::MAX; 20]);
+
+    /// Compare two digests in constant time.
+    ///
+    /// The derived `PartialEq` may exit at the first differing byte, which is fine for ordering and hashing, but
+    /// digest validation against untrusted input should use this instead to avoid leaking timing information.
+    pub fn ct_eq(&self, other: &Sha1Digest) -> bool {
+        let difference = self
+            .0
+            .iter()
+            .zip(other.0.iter())
+            .fold(0, |acc, (a, b)| std::hint::black_box(acc | (a ^ b)));
+
+        difference == 0
+    }
+
+    /// Parse a 40-character hex-encoded digest (upper or lowercase).
+    pub fn from_hex(input: &str) -> Result<Self, Error> {
+        if input.len() == 40 {
+            let mut output = [0; 20];
+            HEXLOWER_PERMISSIVE
+                .decode_mut(input.as_bytes(), &mut output)
+                .map_err(Error::Decoding)?;
+
+            Ok(Self(output))
+        } else {
+            Err(Error::InvalidLength(input.to_string()))
+        }
+    }
+
+    /// Encode the digest as a 40-character lowercase hex string.
+    pub fn to_hex(&self) -> String {
+        HEXLOWER.encode(&self.0)
+    }
 }
 
 impl Display for Sha1Digest {
@@ -225,6 +339,8 @@ impl FromStr for Sha1Digest {
             } else {
                 Err(Self::Err::Invalid(s.to_string()))
             }
+        } else if s.len() == 40 {
+            Self::from_hex(s)
         } else {
             Err(Self::Err::InvalidLength(s.to_string()))
         }
@@ -276,6 +392,90 @@ impl Serialize for Sha1Digest {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Sha256Digest(pub [u8; 32]);
+
+impl Sha256Digest {
+    pub const MIN: Self = Self([u8::MIN; 32]);
+    pub const MAX: Self = Self([u8::MAX; 32]);
+}
+
+impl Display for Sha256Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        BASE32_NOPAD.encode(&self.0).fmt(f)
+    }
+}
+
+impl From<Sha256Digest> for [u8; 32] {
+    fn from(value: Sha256Digest) -> Self {
+        value.0
+    }
+}
+
+impl FromStr for Sha256Digest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() == 52 {
+            let mut output = [0; 32];
+            let count = BASE32_NOPAD
+                .decode_mut(s.as_bytes(), &mut output)
+                .map_err(Error::Decoding)?;
+
+            if count == 32 {
+                Ok(Self(output))
+            } else {
+                Err(Self::Err::InvalidSha256(s.to_string()))
+            }
+        } else {
+            Err(Self::Err::InvalidSha256Length(s.to_string()))
+        }
+    }
+}
+
+impl From<[u8; 32]> for Sha256Digest {
+    fn from(value: [u8; 32]) -> Self {
+        Self(value)
+    }
+}
+
+impl TryFrom<&[u8]> for Sha256Digest {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self(value.try_into().map_err(|_| {
+            Error::InvalidSha256BytesLength(value.to_vec())
+        })?))
+    }
+}
+
+impl<'de> Deserialize<'de> for Sha256Digest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Sha256DigestVisitor;
+
+        impl Visitor<'_> for Sha256DigestVisitor {
+            type Value = Sha256Digest;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("struct Sha256Digest")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse()
+                    .map_err(|_| serde::de::Error::invalid_value(Unexpected::Str(v), &self))
+            }
+        }
+
+        deserializer.deserialize_str(Sha256DigestVisitor)
+    }
+}
+
+impl Serialize for Sha256Digest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 pub mod sha1_base32 {
     use super::Sha1Digest;
     use serde::{
@@ -299,6 +499,54 @@ pub mod sha1_base32 {
     }
 }
 
+/// Serialize a SHA-1 digest as its raw 20 bytes (for binary formats).
+pub mod sha1_bytes {
+    use super::Sha1Digest;
+    use serde::{
+        de::{Deserializer, SeqAccess, Visitor},
+        ser::Serializer,
+    };
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Sha1Digest, D::Error> {
+        struct Sha1BytesVisitor;
+
+        impl<'de> Visitor<'de> for Sha1BytesVisitor {
+            type Value = Sha1Digest;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("20-byte SHA-1 digest")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                v.try_into()
+                    .map_err(|_| serde::de::Error::invalid_length(v.len(), &self))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut output = [0; 20];
+
+                for (i, byte) in output.iter_mut().enumerate() {
+                    *byte = seq
+                        .next_element()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+                }
+
+                if seq.next_element::<u8>()?.is_some() {
+                    Err(serde::de::Error::invalid_length(21, &self))
+                } else {
+                    Ok(Sha1Digest(output))
+                }
+            }
+        }
+
+        deserializer.deserialize_bytes(Sha1BytesVisitor)
+    }
+
+    pub fn serialize<S: Serializer>(value: &Sha1Digest, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&value.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -311,6 +559,119 @@ mod tests {
         assert_eq!(digest_str, digest_string);
     }
 
+    #[test]
+    fn sha1_digest_ct_eq() {
+        let digest: super::Sha1Digest = "VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5".parse().unwrap();
+        let mut other = digest;
+        other.0[19] ^= 1;
+
+        assert!(digest.ct_eq(&digest));
+        assert!(!digest.ct_eq(&other));
+        assert!(!super::Sha1Digest::MIN.ct_eq(&super::Sha1Digest::MAX));
+    }
+
+    #[test]
+    fn sha1_digest_hex() {
+        let digest_str = "VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5";
+        let hex_str = "a9993e364706816aba3e25717850c26c9cd0d89d";
+
+        let digest: super::Sha1Digest = digest_str.parse().unwrap();
+
+        assert_eq!(digest.to_hex(), hex_str);
+        assert_eq!(super::Sha1Digest::from_hex(hex_str).unwrap(), digest);
+        assert_eq!(
+            super::Sha1Digest::from_hex(&hex_str.to_uppercase()).unwrap(),
+            digest
+        );
+        // Parsing falls back to hex for 40-character input, but formatting always uses Base32.
+        assert_eq!(hex_str.parse::<super::Sha1Digest>().unwrap(), digest);
+        assert_eq!(
+            hex_str.to_uppercase().parse::<super::Sha1Digest>().unwrap(),
+            digest
+        );
+        assert_eq!(
+            hex_str.parse::<super::Sha1Digest>().unwrap().to_string(),
+            digest_str
+        );
+        assert!(hex_str[..39].parse::<super::Sha1Digest>().is_err());
+        assert_eq!(
+            super::Sha1Digest::from_hex(hex_str).unwrap().to_string(),
+            digest_str
+        );
+        assert!(super::Sha1Digest::from_hex(digest_str).is_err());
+        assert!(super::Sha1Digest::from_hex(&hex_str.replace('a', "g")).is_err());
+    }
+
+    #[test]
+    fn sha1_computer_incremental() {
+        let computer = super::Sha1Computer::default();
+
+        let expected = computer.digest(&mut b"abc".as_slice()).unwrap();
+
+        computer.update(&mut b"a".as_slice()).unwrap();
+        computer.update(&mut b"bc".as_slice()).unwrap();
+
+        assert_eq!(computer.finish(), expected);
+        assert_eq!(computer.digest(&mut b"abc".as_slice()).unwrap(), expected);
+    }
+
+    #[test]
+    fn round_trip_sha1_bytes() {
+        #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+        struct Value {
+            #[serde(with = "super::sha1_bytes")]
+            digest: super::Sha1Digest,
+        }
+
+        let value = Value {
+            digest: "VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5".parse().unwrap(),
+        };
+
+        let encoded = bincode::serialize(&value).unwrap();
+        let decoded: Value = bincode::deserialize(&encoded).unwrap();
+
+        // An 8-byte length prefix followed by the raw digest.
+        assert_eq!(encoded.len(), 28);
+        assert_eq!(decoded, value);
+
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+
+        let short = bincode::serialize(&[0u8; 19].as_slice()).unwrap();
+        assert!(bincode::deserialize::<Value>(&short).is_err());
+    }
+
+    #[test]
+    fn round_trip_sha256_digest() {
+        let digest_str = "XJ4BNP4PAHH6UQKBIDPF3LRCEOYAGYNDSYLXVHFUCD7WD4QACWWQ";
+
+        let digest: super::Sha256Digest = digest_str.parse().unwrap();
+        let digest_string = digest.to_string();
+
+        assert_eq!(digest_str, digest_string);
+        assert!(digest_str[..32].parse::<super::Sha256Digest>().is_err());
+    }
+
+    #[test]
+    fn compute_sha256_digest() {
+        let computer = super::Sha256Computer::default();
+
+        // Check that the shared hasher is reset between inputs.
+        for _ in 0..2 {
+            assert_eq!(
+                computer.digest_base32(&mut b"abc".as_slice()).unwrap(),
+                "XJ4BNP4PAHH6UQKBIDPF3LRCEOYAGYNDSYLXVHFUCD7WD4QACWWQ"
+            );
+        }
+
+        assert_eq!(
+            super::Sha1Computer::default()
+                .digest_base32(&mut b"abc".as_slice())
+                .unwrap(),
+            "VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5"
+        );
+    }
+
     #[test]
     fn round_trip_digest_valid() {
         let digest_str = "ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4";
@@ -323,13 +684,39 @@ mod tests {
     }
 
     #[test]
-    fn round_trip_digest_invalid() {
-        let digest_str = "HYT52YPEOCHJD5FZINSDYXGQZI22WJ4";
+    fn constructors() {
+        let sha1_digest: super::Sha1Digest = "ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4".parse().unwrap();
+        let invalid_str = "HYT52YPEOCHJD5FZINSDYXGQZI22WJ4";
 
-        let digest: super::Digest = digest_str.parse().unwrap();
-        let digest_string = digest.to_string();
+        let valid = super::Digest::new_valid(sha1_digest);
+        let invalid = super::Digest::new_invalid(invalid_str);
 
-        assert!(!digest.is_valid());
-        assert_eq!(digest_str, digest_string);
+        assert!(valid.is_valid());
+        assert_eq!(valid.valid(), Some(sha1_digest));
+        assert_eq!(valid.invalid(), None);
+
+        assert!(!invalid.is_valid());
+        assert_eq!(invalid.valid(), None);
+        assert_eq!(invalid.invalid(), Some(invalid_str));
+        assert_eq!(invalid, invalid_str.parse().unwrap());
+    }
+
+    #[test]
+    fn round_trip_digest_invalid() {
+        // The second value has the right length but is not valid Base32.
+        for digest_str in [
+            "HYT52YPEOCHJD5FZINSDYXGQZI22WJ4",
+            "HYT52YPEOCHJD5FZINSDYXGQZI22WJ41",
+        ] {
+            let digest: super::Digest = digest_str.parse().unwrap();
+            let digest_string = digest.to_string();
+
+            assert!(!digest.is_valid());
+            assert_eq!(digest_str, digest_string);
+            assert_eq!(
+                serde_json::from_str::<super::Digest>(&format!("\"{digest_str}\"")).unwrap(),
+                digest
+            );
+        }
     }
 }
@@ -1,4 +1,7 @@
-use crate::{digest::Digest, timestamp::Timestamp};
+use crate::{
+    digest::{Digest, Sha1Digest},
+    timestamp::Timestamp,
+};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::str::FromStr;
@@ -33,7 +36,7 @@ impl<'a> UrlParts<'a> {
         format!(
             "http{}://web.archive.org/web/{}{}/{}",
             if https { "s" } else { "" },
-            self.timestamp,
+            self.timestamp.to_seconds_string(),
             if original { "id_" } else { "" },
             self.url
         )
@@ -65,6 +68,16 @@ pub struct ItemInfo<'a> {
     pub expected_digest: Digest<'a>,
 }
 
+impl<'a> ItemInfo<'a> {
+    /// The URL of the original capture and the expected digest (if it is valid).
+    pub fn fetch_target(&self) -> (String, Option<Sha1Digest>) {
+        (
+            self.url_parts.to_wb_url(true, true),
+            self.expected_digest.valid(),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,4 +94,46 @@ mod tests {
 
         assert_eq!(parsed, expected);
     }
+
+    #[test]
+    fn fetch_target() {
+        let digest = "ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4".parse().unwrap();
+        let item_info = ItemInfo {
+            url_parts: UrlParts::new(
+                "https://twitter.com/roman_dmowski99/status/725877225686454272",
+                "20160508215503".parse().unwrap(),
+            ),
+            expected_digest: Digest::Valid(digest),
+        };
+
+        let (url, expected_digest) = item_info.fetch_target();
+
+        assert_eq!(
+            url,
+            "https://web.archive.org/web/20160508215503id_/https://twitter.com/roman_dmowski99/status/725877225686454272"
+        );
+        assert_eq!(expected_digest, Some(digest));
+
+        let item_info = ItemInfo {
+            expected_digest: Digest::Invalid("HYT52YPEOCHJD5FZINSDYXGQZI22WJ4".into()),
+            ..item_info
+        };
+
+        assert_eq!(item_info.fetch_target().1, None);
+    }
+
+    #[test]
+    fn to_wb_url_millis() {
+        let url_parts = UrlParts::new(
+            "https://twitter.com/jack/status/20",
+            "20160508215503123".parse().unwrap(),
+        );
+        let url = url_parts.to_wb_url(true, false);
+
+        assert_eq!(
+            url,
+            "https://web.archive.org/web/20160508215503/https://twitter.com/jack/status/20"
+        );
+        assert!(url.parse::<UrlParts>().is_ok());
+    }
 }
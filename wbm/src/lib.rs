@@ -4,4 +4,6 @@ pub mod digest;
 pub mod item;
 pub mod redirect;
 pub mod surt;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod timestamp;
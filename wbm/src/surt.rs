@@ -4,6 +4,7 @@ use serde::{
 };
 use std::borrow::Cow;
 use std::fmt::Display;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 
 #[derive(thiserror::Error, Debug)]
@@ -18,13 +19,56 @@ pub enum Error {
     UnexpectedUrl(String),
 }
 
+/// Options for converting URLs into SURTs.
+///
+/// The default values match the conversion used for Wayback Machine CDX keys.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SurtOptions {
+    /// Remove `www` domain name parts.
+    pub strip_www: bool,
+    /// Remove a trailing slash from the path.
+    pub strip_trailing_slash: bool,
+    /// Sort query parameters by key (the relative order of parameters with the same key is preserved).
+    pub sort_query: bool,
+    /// Rewrite `x.com` hosts to `twitter.com` (see `Surt::normalize_twitter_host`).
+    pub normalize_twitter_host: bool,
+}
+
+impl Default for SurtOptions {
+    fn default() -> Self {
+        Self {
+            strip_www: true,
+            strip_trailing_slash: true,
+            sort_query: true,
+            normalize_twitter_host: false,
+        }
+    }
+}
+
+/// Convert a URL into a SURT string.
+pub fn url_to_surt(url: &str, options: &SurtOptions) -> Result<String, Error> {
+    Surt::from_url_with(url, *options).map(|surt| surt.to_string())
+}
+
+/// Convert a SURT string into a canonical URL.
+pub fn surt_to_url(surt: &str) -> Result<String, Error> {
+    Surt::parse_str(surt).map(|surt| surt.canonical_url().to_string())
+}
+
 /// Simplified Sort-friendly URI Reordering Transform representation.
 ///
 /// Currently only implements features necessary to handle Wayback Machine CDX results.
+///
+/// Internationalized domain names are always stored in their ASCII (punycode) form, so URLs with Unicode hosts and
+/// their punycode equivalents have the same SURT.
+///
+/// Non-default ports are included after the domain name (e.g. `com,example:8080)/path`). IP address hosts are
+/// represented as a single domain name part (e.g. `192.168.0.1)/path` or `[::1])/path`).
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Surt<'a> {
     source: Cow<'a, str>,
     domain_name_part_lens: Vec<u8>,
+    port_len: u8,
 }
 
 impl<'a> Surt<'a> {
@@ -32,28 +76,122 @@ impl<'a> Surt<'a> {
         &self.source
     }
 
+    /// The length of the domain name parts including separators and the closing parenthesis or port separator.
+    fn domain_name_len(&'a self) -> usize {
+        self.domain_name_part_lens.len()
+            + self
+                .domain_name_part_lens
+                .iter()
+                .map(|len| *len as usize)
+                .sum::<usize>()
+    }
+
     fn path_start(&'a self) -> usize {
-        self.domain_name_part_lens.len() + self.domain_name_part_lens.iter().sum::<u8>() as usize
+        if self.port_len == 0 {
+            self.domain_name_len()
+        } else {
+            self.domain_name_len() + self.port_len as usize + 1
+        }
     }
 
     pub fn domain_name_parts(&'a self) -> DomainNamePartIter<'a> {
         DomainNamePartIter {
-            source: &self.source[0..self.path_start() - 1],
+            source: &self.source[0..self.domain_name_len() - 1],
             domain_name_part_lens: self.domain_name_part_lens.iter(),
         }
     }
 
+    /// The domain name parts, with any punycode parts decoded to Unicode.
+    ///
+    /// Parts that are not valid punycode are returned unchanged.
+    pub fn domain_name_parts_unicode(&'a self) -> impl DoubleEndedIterator<Item = Cow<'a, str>> {
+        self.domain_name_parts().map(|part| {
+            if part.starts_with("xn--") {
+                let (decoded, result) = idna::domain_to_unicode(part);
+
+                if result.is_ok() {
+                    Cow::Owned(decoded)
+                } else {
+                    Cow::Borrowed(part)
+                }
+            } else {
+                Cow::Borrowed(part)
+            }
+        })
+    }
+
+    pub fn port(&'a self) -> Option<u16> {
+        if self.port_len == 0 {
+            None
+        } else {
+            let start = self.domain_name_len();
+
+            self.source[start..start + self.port_len as usize]
+                .parse()
+                .ok()
+        }
+    }
+
+    /// The path, not including any query.
     pub fn path(&'a self) -> &'a str {
-        &self.source[self.path_start()..]
+        let path = &self.source[self.path_start()..];
+
+        path.split_once('?').map_or(path, |(path, _)| path)
+    }
+
+    /// The query (not including the `?`), if there is one.
+    ///
+    /// For SURTs created from URLs, the query is normalized: parameters are sorted by key and values are re-encoded
+    /// (see `canonical_url_decoded`).
+    pub fn query(&'a self) -> Option<&'a str> {
+        self.source[self.path_start()..]
+            .split_once('?')
+            .map(|(_, query)| query)
     }
 
     pub fn parse_str(input: &'a str) -> Result<Self, Error> {
         let mut domain_name_part_lens = Vec::with_capacity(2);
         let mut len = 0;
+        let mut port_len = None;
+        let mut has_dot = false;
+
+        // A bracketed IPv6 address is a single domain name part.
+        let rest = if input.starts_with('[') {
+            let end = input
+                .find(']')
+                .filter(|end| input[1..*end].parse::<Ipv6Addr>().is_ok())
+                .ok_or_else(|| Error::InvalidSurt(input.to_string()))?
+                + 1;
+
+            if !input[end..].starts_with([':', ')']) {
+                return Err(Error::InvalidSurt(input.to_string()));
+            }
 
-        for ch in input.chars() {
-            if ch.is_ascii_alphanumeric() || ch == '-' {
+            len = end as u8;
+
+            &input[end..]
+        } else {
+            input
+        };
+
+        for ch in rest.chars() {
+            if let Some(port_len) = port_len.as_mut() {
+                if ch.is_ascii_digit() && *port_len < 5 {
+                    *port_len += 1;
+                } else if ch == ')' {
+                    break;
+                } else {
+                    return Err(Error::InvalidSurt(input.to_string()));
+                }
+            } else if ch.is_ascii_alphanumeric() || ch == '-' {
                 len += 1;
+            } else if ch == '.' {
+                has_dot = true;
+                len += 1;
+            } else if ch == ':' {
+                domain_name_part_lens.push(len);
+
+                port_len = Some(0);
             } else if ch == ',' {
                 domain_name_part_lens.push(len);
 
@@ -67,35 +205,96 @@ impl<'a> Surt<'a> {
             }
         }
 
-        Ok(Self {
+        let surt = Self {
             source: input.into(),
             domain_name_part_lens,
-        })
+            port_len: port_len.unwrap_or(0),
+        };
+
+        // If there is a port separator, the port must be valid and followed by a closing parenthesis.
+        let invalid_port = port_len.is_some()
+            && (surt.port().is_none()
+                || surt.source.as_bytes().get(surt.path_start() - 1) != Some(&b')'));
+
+        // Dots are only allowed in an IPv4 address, which must be the only domain name part.
+        let invalid_dot = has_dot
+            && (surt.domain_name_part_lens.len() != 1
+                || surt
+                    .domain_name_parts()
+                    .next()
+                    .and_then(|part| part.parse::<Ipv4Addr>().ok())
+                    .is_none());
+
+        if invalid_port || invalid_dot {
+            Err(Error::InvalidSurt(input.to_string()))
+        } else {
+            Ok(surt)
+        }
     }
 
     pub fn into_owned(self) -> Surt<'static> {
         Surt {
             source: self.source.into_owned().into(),
             domain_name_part_lens: self.domain_name_part_lens,
+            port_len: self.port_len,
         }
     }
 
     pub fn canonical_url(&'a self) -> SurtCanonicalUrl<'a> {
-        SurtCanonicalUrl { source: self }
+        SurtCanonicalUrl {
+            source: self,
+            decode_query: false,
+        }
+    }
+
+    /// A canonical URL with the SURT encoding of query values reversed.
+    ///
+    /// When a SURT is created from a URL, literal `+` characters in query values are written as `%20`, and spaces as
+    /// `+`. This undoes that swap (so that `%20` becomes `%2b`), as well as the decoding of `^`. Note that the
+    /// original ordering of query parameters cannot be recovered.
+    pub fn canonical_url_decoded(&'a self) -> SurtCanonicalUrl<'a> {
+        SurtCanonicalUrl {
+            source: self,
+            decode_query: true,
+        }
+    }
+
+    /// Rewrite an `x.com` host (including subdomains) to `twitter.com`.
+    ///
+    /// The Wayback Machine has captures of the same resources under both domains, so this is necessary if we want
+    /// them to be treated as equivalent.
+    pub fn normalize_twitter_host(&mut self) {
+        const X_PREFIX: &str = "com,x";
+        const TWITTER_PREFIX: &str = "com,twitter";
+
+        if self.domain_name_part_lens.starts_with(&[3, 1]) && self.source.starts_with(X_PREFIX) {
+            let mut source = String::with_capacity(self.source.len() + TWITTER_PREFIX.len());
+            source.push_str(TWITTER_PREFIX);
+            source.push_str(&self.source[X_PREFIX.len()..]);
+
+            self.source = source.into();
+            self.domain_name_part_lens[1] = 7;
+        }
     }
 }
 
 impl Surt<'static> {
     pub fn from_url(input: &str) -> Result<Self, Error> {
+        Self::from_url_with(input, SurtOptions::default())
+    }
+
+    pub fn from_url_with(input: &str, options: SurtOptions) -> Result<Self, Error> {
         let url: url::Url = input.to_lowercase().parse()?;
 
-        match (url.scheme(), url.domain()) {
-            ("http" | "https", Some(domain_name)) if url.port().is_none() => {
+        let (mut source, domain_name_part_lens) = match (url.scheme(), url.host()) {
+            // The URL parser applies IDNA processing to hosts for these schemes, so the domain name here is always in
+            // ASCII (punycode) form, whether the input used Unicode or punycode.
+            ("http" | "https", Some(url::Host::Domain(domain_name))) if domain_name.is_ascii() => {
                 let mut source = String::new();
                 let mut domain_name_part_lens = Vec::with_capacity(2);
 
                 for domain_name_part in domain_name.split('.').rev() {
-                    if domain_name_part != "www" {
+                    if !options.strip_www || domain_name_part != "www" {
                         source.push_str(domain_name_part);
                         source.push(',');
 
@@ -108,45 +307,82 @@ impl Surt<'static> {
                 }
 
                 source.pop();
-                source.push(')');
-                source.push_str(&Self::decode_path(url.path()));
 
-                if source.ends_with("/") {
-                    source.pop();
-                }
+                (source, domain_name_part_lens)
+            }
+            // IP addresses are a single domain name part, and are not reversed.
+            ("http" | "https", Some(url::Host::Ipv4(address))) => {
+                let source = address.to_string();
+                let len = source.len() as u8;
 
-                let mut query_pairs = url.query_pairs().collect::<Vec<_>>();
+                (source, vec![len])
+            }
+            ("http" | "https", Some(url::Host::Ipv6(address))) => {
+                let source = format!("[{address}]");
+                let len = source.len() as u8;
 
-                if !query_pairs.is_empty() {
-                    query_pairs.sort_by_key(|(key, _)| key.clone());
+                (source, vec![len])
+            }
+            _ => return Err(Error::UnexpectedUrl(input.to_string())),
+        };
 
-                    source.push('?');
+        // The URL parser omits the port if it is the default for the scheme.
+        let port_len = match url.port() {
+            Some(port) => {
+                let port = port.to_string();
+                source.push(':');
+                source.push_str(&port);
 
-                    let mut first = true;
+                port.len() as u8
+            }
+            None => 0,
+        };
 
-                    for (key, value) in query_pairs {
-                        if first {
-                            first = false;
-                        } else {
-                            source.push('&');
-                        }
+        source.push(')');
+        source.push_str(&Self::decode_path(url.path()));
 
-                        source.push_str(&key);
-                        source.push('=');
+        if options.strip_trailing_slash && source.ends_with("/") {
+            source.pop();
+        }
 
-                        if !value.is_empty() {
-                            source.push_str(&Self::decode_query_value(&value));
-                        }
-                    }
+        let mut query_pairs = url.query_pairs().collect::<Vec<_>>();
+
+        if !query_pairs.is_empty() {
+            if options.sort_query {
+                query_pairs.sort_by_key(|(key, _)| key.clone());
+            }
+
+            source.push('?');
+
+            let mut first = true;
+
+            for (key, value) in query_pairs {
+                if first {
+                    first = false;
+                } else {
+                    source.push('&');
                 }
 
-                Ok(Self {
-                    source: source.into(),
-                    domain_name_part_lens,
-                })
+                source.push_str(&key);
+                source.push('=');
+
+                if !value.is_empty() {
+                    source.push_str(&Self::decode_query_value(&value));
+                }
             }
-            _ => Err(Error::UnexpectedUrl(input.to_string())),
         }
+
+        let mut surt = Self {
+            source: source.into(),
+            domain_name_part_lens,
+            port_len,
+        };
+
+        if options.normalize_twitter_host {
+            surt.normalize_twitter_host();
+        }
+
+        Ok(surt)
     }
 
     fn decode_path(value: &str) -> String {
@@ -170,6 +406,11 @@ impl Surt<'static> {
             .replace('\n', "%0a")
             .replace("%5e", "^")
     }
+
+    /// Reverse the transformation applied by `decode_query_value`.
+    fn encode_query_value(value: &str) -> String {
+        value.replace("%20", "%2b").replace('^', "%5e")
+    }
 }
 
 impl<'a> Display for Surt<'a> {
@@ -223,6 +464,7 @@ impl<'a> Serialize for Surt<'a> {
 
 pub struct SurtCanonicalUrl<'a> {
     source: &'a Surt<'a>,
+    decode_query: bool,
 }
 
 impl Display for SurtCanonicalUrl<'_> {
@@ -242,8 +484,41 @@ impl Display for SurtCanonicalUrl<'_> {
             f.write_str(first_part)?;
         }
 
+        if let Some(port) = self.source.port() {
+            write!(f, ":{port}")?;
+        }
+
         f.write_str(self.source.path())?;
 
+        if let Some(query) = self.source.query() {
+            f.write_str("?")?;
+
+            if self.decode_query {
+                let mut first = true;
+
+                for pair in query.split('&') {
+                    if first {
+                        first = false;
+                    } else {
+                        f.write_str("&")?;
+                    }
+
+                    match pair.split_once('=') {
+                        Some((key, value)) => {
+                            f.write_str(key)?;
+                            f.write_str("=")?;
+                            f.write_str(&Surt::encode_query_value(value))?;
+                        }
+                        None => {
+                            f.write_str(pair)?;
+                        }
+                    }
+                }
+            } else {
+                f.write_str(query)?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -261,7 +536,8 @@ impl<'a> Iterator for DomainNamePartIter<'a> {
             let len = *len as usize;
             let part = &self.source[0..len];
 
-            self.source = &self.source[len..];
+            // Skip the separator, if there is one.
+            self.source = &self.source[(len + 1).min(self.source.len())..];
 
             part
         })
@@ -272,9 +548,10 @@ impl<'a> DoubleEndedIterator for DomainNamePartIter<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
         self.domain_name_part_lens.next_back().map(|len| {
             let len = *len as usize;
-            let part = &self.source[self.source.len() - len..];
+            let start = self.source.len() - len;
+            let part = &self.source[start..];
 
-            self.source = &self.source[0..self.source.len()];
+            self.source = &self.source[0..start.saturating_sub(1)];
 
             part
         })
@@ -316,6 +593,231 @@ mod tests {
         assert_eq!(parsed.canonical_url().to_string(), expected);
     }
 
+    #[test]
+    fn url_surt_round_trip() {
+        let pairs = [
+            (
+                "https://twitter.com/farleftwatch/status/999825423977639936",
+                "com,twitter)/farleftwatch/status/999825423977639936",
+            ),
+            (
+                "https://mobile.twitter.com/richardbspencer",
+                "com,twitter,mobile)/richardbspencer",
+            ),
+            (
+                "https://twitter.com/i/web/status/1787642853474087122",
+                "com,twitter)/i/web/status/1787642853474087122",
+            ),
+        ];
+
+        for (url, surt) in pairs {
+            assert_eq!(url_to_surt(url, &SurtOptions::default()).unwrap(), surt);
+            assert_eq!(surt_to_url(surt).unwrap(), url);
+        }
+    }
+
+    #[test]
+    fn from_url_idn() {
+        let unicode = Surt::from_url("http://müller.example/x").unwrap();
+        let unicode_upper = Surt::from_url("http://MÜLLER.example/x").unwrap();
+        let punycode = Surt::from_url("http://xn--mller-kva.example/x").unwrap();
+        let punycode_upper = Surt::from_url("http://XN--MLLER-KVA.example/x").unwrap();
+
+        assert_eq!(unicode.as_str(), "example,xn--mller-kva)/x");
+        assert_eq!(unicode, punycode);
+        assert_eq!(unicode_upper, punycode);
+        assert_eq!(punycode_upper, punycode);
+        assert_eq!(
+            unicode.domain_name_parts().collect::<Vec<_>>(),
+            vec!["example", "xn--mller-kva"]
+        );
+        assert_eq!(
+            unicode.domain_name_parts_unicode().collect::<Vec<_>>(),
+            vec!["example", "müller"]
+        );
+    }
+
+    #[test]
+    fn domain_name_parts_unicode() {
+        let surt = Surt::from_url("https://www.café.com/menu").unwrap();
+
+        assert_eq!(surt.as_str(), "com,xn--caf-dma)/menu");
+        assert_eq!(surt, "com,xn--caf-dma)/menu".parse().unwrap());
+        assert_eq!(
+            surt.domain_name_parts_unicode().rev().collect::<Vec<_>>(),
+            vec!["café", "com"]
+        );
+        assert_eq!(
+            surt.canonical_url().to_string(),
+            "https://xn--caf-dma.com/menu"
+        );
+    }
+
+    #[test]
+    fn from_url_port() {
+        let surt = Surt::from_url("http://www.example.com:8080/Path/").unwrap();
+
+        assert_eq!(surt.as_str(), "com,example:8080)/path");
+        assert_eq!(surt.port(), Some(8080));
+        assert_eq!(surt.path(), "/path");
+        assert_eq!(
+            surt.domain_name_parts().collect::<Vec<_>>(),
+            vec!["com", "example"]
+        );
+        assert_eq!(
+            surt.canonical_url().to_string(),
+            "https://example.com:8080/path"
+        );
+        assert_eq!(surt.as_str().parse::<Surt>().unwrap(), surt);
+
+        let default_port = Surt::from_url("http://example.com:80/path").unwrap();
+        let no_port = Surt::from_url("http://example.com/path").unwrap();
+
+        assert_eq!(default_port.as_str(), "com,example)/path");
+        assert_eq!(default_port.port(), None);
+        assert_eq!(default_port, no_port);
+
+        assert!("com,example:)/path".parse::<Surt>().is_err());
+        assert!("com,example:99999)/path".parse::<Surt>().is_err());
+        assert!("com,example:80a)/path".parse::<Surt>().is_err());
+        assert!("com,example:80".parse::<Surt>().is_err());
+    }
+
+    #[test]
+    fn from_url_ip_host() {
+        let ipv4 = Surt::from_url("http://192.168.0.1/Path").unwrap();
+
+        assert_eq!(ipv4.as_str(), "192.168.0.1)/path");
+        assert_eq!(
+            ipv4.domain_name_parts().collect::<Vec<_>>(),
+            vec!["192.168.0.1"]
+        );
+        assert_eq!(ipv4.canonical_url().to_string(), "https://192.168.0.1/path");
+        assert_eq!(ipv4.as_str().parse::<Surt>().unwrap(), ipv4);
+
+        let ipv6 = Surt::from_url("http://[2001:DB8::1]:8080/path").unwrap();
+
+        assert_eq!(ipv6.as_str(), "[2001:db8::1]:8080)/path");
+        assert_eq!(
+            ipv6.domain_name_parts().collect::<Vec<_>>(),
+            vec!["[2001:db8::1]"]
+        );
+        assert_eq!(ipv6.port(), Some(8080));
+        assert_eq!(ipv6.path(), "/path");
+        assert_eq!(
+            ipv6.canonical_url().to_string(),
+            "https://[2001:db8::1]:8080/path"
+        );
+        assert_eq!(ipv6.as_str().parse::<Surt>().unwrap(), ipv6);
+
+        assert!("com.example)/path".parse::<Surt>().is_err());
+        assert!("1,192.168.0.1)/path".parse::<Surt>().is_err());
+        assert!("[2001:db8::1]/path".parse::<Surt>().is_err());
+        assert!("[not-an-address])/path".parse::<Surt>().is_err());
+    }
+
+    #[test]
+    fn query() {
+        let surt = Surt::from_url("https://example.com/search?q=a+b%2Bc&lang=en&x=%5E").unwrap();
+
+        assert_eq!(surt.as_str(), "com,example)/search?lang=en&q=a+b%20c&x=^");
+        assert_eq!(surt.path(), "/search");
+        assert_eq!(surt.query(), Some("lang=en&q=a+b%20c&x=^"));
+        assert_eq!(
+            surt.canonical_url().to_string(),
+            "https://example.com/search?lang=en&q=a+b%20c&x=^"
+        );
+        assert_eq!(
+            surt.canonical_url_decoded().to_string(),
+            "https://example.com/search?lang=en&q=a+b%2bc&x=%5e"
+        );
+
+        let decoded = surt.canonical_url_decoded().to_string();
+        assert_eq!(Surt::from_url(&decoded).unwrap(), surt);
+
+        let no_query = Surt::from_url("https://example.com/search").unwrap();
+
+        assert_eq!(no_query.path(), "/search");
+        assert_eq!(no_query.query(), None);
+    }
+
+    #[test]
+    fn normalize_twitter_host() {
+        let mut surt = "com,x)/farleftwatch/status/999825423977639936"
+            .parse::<Surt>()
+            .unwrap();
+        let expected = "com,twitter)/farleftwatch/status/999825423977639936"
+            .parse::<Surt>()
+            .unwrap();
+
+        surt.normalize_twitter_host();
+
+        assert_eq!(surt, expected);
+
+        let mut surt = "com,x,mobile)/farleftwatch".parse::<Surt>().unwrap();
+        let expected = "com,twitter,mobile)/farleftwatch".parse::<Surt>().unwrap();
+
+        surt.normalize_twitter_host();
+
+        assert_eq!(surt, expected);
+
+        let mut surt = "com,xyz)/farleftwatch".parse::<Surt>().unwrap();
+        let expected = surt.clone();
+
+        surt.normalize_twitter_host();
+
+        assert_eq!(surt, expected);
+    }
+
+    #[test]
+    fn from_url_normalize_twitter_host() {
+        let options = SurtOptions {
+            normalize_twitter_host: true,
+            ..Default::default()
+        };
+
+        let from_x = Surt::from_url_with("https://x.com/farleftwatch/", options).unwrap();
+        let from_twitter = Surt::from_url("https://twitter.com/farleftwatch/").unwrap();
+
+        assert_eq!(from_x, from_twitter);
+    }
+
+    #[test]
+    fn from_url_preserving_options() {
+        let input = "https://www.example.com/path/?b=2&a=1";
+        let options = SurtOptions {
+            strip_www: false,
+            strip_trailing_slash: false,
+            sort_query: false,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Surt::from_url(input).unwrap().as_str(),
+            "com,example)/path?a=1&b=2"
+        );
+        assert_eq!(
+            Surt::from_url_with(input, options).unwrap().as_str(),
+            "com,example,www)/path/?b=2&a=1"
+        );
+    }
+
+    #[test]
+    fn domain_name_parts() {
+        let surt = "com,twitter,mobile)/richardbspencer"
+            .parse::<Surt>()
+            .unwrap();
+
+        assert_eq!(
+            surt.domain_name_parts().collect::<Vec<_>>(),
+            vec!["com", "twitter", "mobile"]
+        );
+        assert_eq!(
+            surt.domain_name_parts().rev().collect::<Vec<_>>(),
+            vec!["mobile", "twitter", "com"]
+        );
+    }
+
     #[test]
     fn from_url_examples() {
         let contents = include_str!("../../examples/cdx/1706619334645856.json");
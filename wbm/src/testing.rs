@@ -0,0 +1,18 @@
+//! Test fixtures shared by the crates in this workspace.
+
+use crate::digest::Sha1Digest;
+use sha1::{Digest, Sha1};
+
+/// The SHA-1 digest of the given content.
+pub fn sha1_digest<T: AsRef<[u8]>>(content: T) -> Sha1Digest {
+    Sha1Digest(Sha1::digest(content.as_ref()).into())
+}
+
+/// The digest and raw content of a minimal snapshot for the given tweet ID.
+///
+/// The content has no author ID and ends with the default closing whitespace (`\r\r\n`).
+pub fn snapshot_content(id: u64) -> (Sha1Digest, String) {
+    let content = format!("{{\"data\":{{\"id\":\"{id}\"}}}}\r\r\n");
+
+    (sha1_digest(&content), content)
+}
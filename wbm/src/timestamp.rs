@@ -1,4 +1,4 @@
-use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use chrono::{DateTime, Months, NaiveDateTime, SubsecRound, TimeDelta, Utc};
 use serde::{
     de::{Deserialize, Deserializer, Unexpected, Visitor},
     ser::{Serialize, Serializer},
@@ -7,6 +7,8 @@ use std::fmt::Display;
 use std::str::FromStr;
 
 const TIMESTAMP_FMT: &str = "%Y%m%d%H%M%S";
+const TIMESTAMP_MILLIS_FMT: &str = "%Y%m%d%H%M%S%3f";
+const TIMESTAMP_PREFIX_PADDING: &str = "0101000000";
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -20,9 +22,14 @@ pub enum Error {
     SubsecondDateTime(DateTime<Utc>),
     #[error("Invalid value")]
     InvalidValue(String),
+    #[error("Invalid timestamp range")]
+    InvalidRange(String),
 }
 
 /// Represents a Wayback Machine URL timestamp.
+///
+/// Most timestamps have 14 digits (second precision), but some captures have 17-digit timestamps with milliseconds.
+/// The extra digits are only included in the string representation when the millisecond value is non-zero.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Timestamp(DateTime<Utc>);
 
@@ -36,11 +43,57 @@ impl Timestamp {
             None
         })
     }
+
+    /// The 14-digit representation, truncated to second precision.
+    ///
+    /// Wayback Machine URLs and CDX query parameters require this form, even for timestamps with milliseconds.
+    pub fn to_seconds_string(&self) -> String {
+        self.0.format(TIMESTAMP_FMT).to_string()
+    }
+
+    pub fn to_rfc3339(&self) -> String {
+        self.0.to_rfc3339()
+    }
+
+    /// Parse an RFC 3339 date-time string, which must not have more than millisecond precision.
+    pub fn from_rfc3339(input: &str) -> Result<Self, Error> {
+        DateTime::parse_from_rfc3339(input)?
+            .with_timezone(&Utc)
+            .try_into()
+    }
+
+    /// Parse a coarse timestamp prefix (e.g. `2020` or `202003`) into the range of instants it represents.
+    ///
+    /// The prefix must have 4, 6, 8, 10, or 12 digits (year through minute precision).
+    pub fn parse_prefix(input: &str) -> Result<TimestampRange, Error> {
+        match input.len() {
+            4 | 6 | 8 | 10 | 12 => {
+                let padded = format!("{input}{}", &TIMESTAMP_PREFIX_PADDING[input.len() - 4..]);
+                let start = NaiveDateTime::parse_from_str(&padded, TIMESTAMP_FMT)?.and_utc();
+
+                let end = match input.len() {
+                    4 => start.checked_add_months(Months::new(12)),
+                    6 => start.checked_add_months(Months::new(1)),
+                    8 => start.checked_add_signed(TimeDelta::days(1)),
+                    10 => start.checked_add_signed(TimeDelta::hours(1)),
+                    _ => start.checked_add_signed(TimeDelta::minutes(1)),
+                }
+                .ok_or_else(|| Error::InvalidValue(input.to_string()))?;
+
+                Ok(TimestampRange::new(Some(Self(start)), Some(Self(end))))
+            }
+            _ => Err(Error::InvalidLength(input.to_string())),
+        }
+    }
 }
 
 impl Display for Timestamp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0.format(TIMESTAMP_FMT))
+        if self.0.timestamp_subsec_millis() == 0 {
+            write!(f, "{}", self.0.format(TIMESTAMP_FMT))
+        } else {
+            write!(f, "{}", self.0.format(TIMESTAMP_MILLIS_FMT))
+        }
     }
 }
 
@@ -48,7 +101,7 @@ impl TryFrom<DateTime<Utc>> for Timestamp {
     type Error = Error;
 
     fn try_from(value: DateTime<Utc>) -> Result<Self, Self::Error> {
-        let truncated = value.trunc_subsecs(0);
+        let truncated = value.trunc_subsecs(3);
 
         if truncated == value {
             Ok(Self(value))
@@ -83,13 +136,20 @@ impl FromStr for Timestamp {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() == 14 {
-            let date_time = NaiveDateTime::parse_from_str(s, TIMESTAMP_FMT)?.and_utc();
+        let fmt = match s.len() {
+            14 => Some(TIMESTAMP_FMT),
+            17 => Some(TIMESTAMP_MILLIS_FMT),
+            _ => None,
+        };
+
+        if let Some(fmt) = fmt {
+            let date_time = NaiveDateTime::parse_from_str(s, fmt)?.and_utc();
             let timestamp = Timestamp(date_time);
 
             // This validation confirms that the input can be round-tripped through our representation. I've never seen
             // an input where this fails, and the check is expensive enough that I think it deserves a feature flag
-            // (for example in one quick test it makes a 13-minute job take over 15 minutes).
+            // (for example in one quick test it makes a 13-minute job take over 15 minutes). Note that 17-digit inputs
+            // with zero milliseconds do not round-trip, and will be rejected when this check is enabled.
             #[cfg(feature = "validation")]
             if timestamp.to_string() == s {
                 Ok(timestamp)
@@ -132,6 +192,75 @@ impl Serialize for Timestamp {
     }
 }
 
+/// A range of timestamps, with an inclusive start and exclusive end.
+///
+/// Either bound may be omitted, in which case the range is unbounded on that side. The string representation is of
+/// the form `20200101000000..20210101000000`, where either side may be empty.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct TimestampRange {
+    pub start: Option<Timestamp>,
+    pub end: Option<Timestamp>,
+}
+
+impl TimestampRange {
+    pub fn new(start: Option<Timestamp>, end: Option<Timestamp>) -> Self {
+        Self { start, end }
+    }
+
+    pub fn contains(&self, timestamp: Timestamp) -> bool {
+        self.start.is_none_or(|start| start <= timestamp)
+            && self.end.is_none_or(|end| timestamp < end)
+    }
+
+    /// Indicates whether no timestamp can be contained in this range.
+    pub fn is_empty(&self) -> bool {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => start >= end,
+            _ => false,
+        }
+    }
+}
+
+impl Display for TimestampRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(start) = self.start {
+            start.fmt(f)?;
+        }
+
+        write!(f, "..")?;
+
+        if let Some(end) = self.end {
+            end.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for TimestampRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| Error::InvalidRange(s.to_string()))?;
+
+        let start = if start.is_empty() {
+            None
+        } else {
+            Some(start.parse()?)
+        };
+
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(end.parse()?)
+        };
+
+        Ok(Self { start, end })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::{SubsecRound, Utc};
@@ -145,4 +274,125 @@ mod tests {
 
         assert_eq!(timestamp, timestamp_parsed);
     }
+
+    #[test]
+    fn round_trip_14_digits() {
+        let timestamp_str = "20200101000000";
+        let timestamp: super::Timestamp = timestamp_str.parse().unwrap();
+
+        assert_eq!(timestamp.to_string(), timestamp_str);
+    }
+
+    #[test]
+    fn round_trip_17_digits() {
+        let timestamp_str = "20200101000000123";
+        let timestamp: super::Timestamp = timestamp_str.parse().unwrap();
+
+        assert_eq!(timestamp.to_string(), timestamp_str);
+        assert_eq!(
+            chrono::DateTime::<Utc>::from(timestamp).timestamp_subsec_millis(),
+            123
+        );
+        assert!("202001010000001".parse::<super::Timestamp>().is_err());
+        assert_eq!(timestamp.to_seconds_string(), "20200101000000");
+    }
+
+    #[test]
+    fn timestamp_range() {
+        let a: super::Timestamp = "20200101000000".parse().unwrap();
+        let b: super::Timestamp = "20200601000000".parse().unwrap();
+        let c: super::Timestamp = "20210101000000".parse().unwrap();
+
+        let bounded: super::TimestampRange = "20200101000000..20210101000000".parse().unwrap();
+        assert!(bounded.contains(a));
+        assert!(bounded.contains(b));
+        assert!(!bounded.contains(c));
+        assert!(!bounded.is_empty());
+
+        let from: super::TimestampRange = "20200601000000..".parse().unwrap();
+        assert!(!from.contains(a));
+        assert!(from.contains(b));
+        assert!(from.contains(c));
+        assert_eq!(from.end, None);
+
+        let until: super::TimestampRange = "..20200601000000".parse().unwrap();
+        assert!(until.contains(a));
+        assert!(!until.contains(b));
+        assert_eq!(until.start, None);
+
+        let unbounded: super::TimestampRange = "..".parse().unwrap();
+        assert!(unbounded.contains(a));
+        assert_eq!(unbounded, super::TimestampRange::default());
+
+        let empty: super::TimestampRange = "20200601000000..20200601000000".parse().unwrap();
+        assert!(empty.is_empty());
+        assert!(!empty.contains(b));
+
+        for range in [bounded, from, until, unbounded, empty] {
+            assert_eq!(
+                range.to_string().parse::<super::TimestampRange>().unwrap(),
+                range
+            );
+        }
+
+        assert!("20200101000000".parse::<super::TimestampRange>().is_err());
+        assert!("2020..".parse::<super::TimestampRange>().is_err());
+    }
+
+    #[test]
+    fn parse_prefix() {
+        let leap_february = super::Timestamp::parse_prefix("202002").unwrap();
+        assert_eq!(leap_february.to_string(), "20200201000000..20200301000000");
+        assert!(leap_february.contains("20200229235959".parse().unwrap()));
+        assert!(!leap_february.contains("20200301000000".parse().unwrap()));
+
+        let year_end = super::Timestamp::parse_prefix("202312").unwrap();
+        assert_eq!(year_end.to_string(), "20231201000000..20240101000000");
+
+        let last_day = super::Timestamp::parse_prefix("20231231").unwrap();
+        assert_eq!(last_day.to_string(), "20231231000000..20240101000000");
+
+        let year = super::Timestamp::parse_prefix("2020").unwrap();
+        assert_eq!(year.to_string(), "20200101000000..20210101000000");
+
+        let minute = super::Timestamp::parse_prefix("202312312359").unwrap();
+        assert_eq!(minute.to_string(), "20231231235900..20240101000000");
+
+        assert!(super::Timestamp::parse_prefix("20200").is_err());
+        assert!(super::Timestamp::parse_prefix("20200101000000").is_err());
+        assert!(super::Timestamp::parse_prefix("202013").is_err());
+        assert!(super::Timestamp::parse_prefix("2020ab").is_err());
+    }
+
+    #[test]
+    fn round_trip_rfc3339() {
+        let timestamp: super::Timestamp = "20200229123456".parse().unwrap();
+
+        assert_eq!(timestamp.to_rfc3339(), "2020-02-29T12:34:56+00:00");
+        assert_eq!(
+            super::Timestamp::from_rfc3339(&timestamp.to_rfc3339()).unwrap(),
+            timestamp
+        );
+        assert_eq!(
+            super::Timestamp::from_rfc3339("2020-02-29T13:34:56.123+01:00")
+                .unwrap()
+                .to_string(),
+            "20200229123456123"
+        );
+        assert!(super::Timestamp::from_rfc3339("2020-02-29T12:34:56.1234Z").is_err());
+        assert!(super::Timestamp::from_rfc3339("20200229123456").is_err());
+    }
+
+    #[test]
+    fn try_from_date_time_millis() {
+        let date_time = Utc::now();
+
+        assert!(super::Timestamp::try_from(date_time.trunc_subsecs(3)).is_ok());
+        assert!(
+            super::Timestamp::try_from(
+                date_time.trunc_subsecs(3) + chrono::TimeDelta::microseconds(1)
+            )
+            .is_err()
+        );
+    }
 }
@@ -1,12 +1,17 @@
 use archivindex_wbm::digest::Sha1Digest;
-use archivindex_wxj::lines::{Snapshot, SnapshotLine};
-use birdsite::model::wxj::{TweetSnapshot, data, flat};
+use archivindex_wxj::lines::{
+    SnapshotLine, SnapshotLineExplanation,
+    diff::{Diff, DiffCounts, DiffEntry},
+    stats::{LineStats, UserCounts},
+    tweet::TweetSnapshotKind,
+};
 use cli_helpers::prelude::*;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 mod cdx;
+mod progress;
 mod snapshot;
 
 #[tokio::main]
@@ -15,18 +20,22 @@ async fn main() -> Result<(), Error> {
     opts.verbose.init_logging()?;
 
     match opts.command {
-        Command::Validate { input } => {
+        Command::Validate { input, progress } => {
             let mut count = 0;
             let mut hasher = Default::default();
+            let mut progress = progress::Progress::new(progress);
 
             for path in input {
-                let reader = BufReader::new(zstd::Decoder::new(File::open(&path)?)?);
+                let file = File::open(&path)?;
+                let len = file.metadata()?.len();
+                let reader = BufReader::new(zstd::Decoder::new(progress.reader(file, len))?);
                 log::info!("Reading file: {}", path.as_os_str().to_string_lossy());
 
                 let mut last_digest = Sha1Digest::MIN;
 
                 for line in reader.lines() {
                     let line = line?;
+                    progress.tick();
 
                     let snapshot_line = SnapshotLine::parse(&line)?;
 
@@ -77,6 +86,8 @@ async fn main() -> Result<(), Error> {
             snapshots,
             output,
             compression,
+            no_verify_sorted,
+            progress,
         } => {
             const FLAT_FILE_NAME: &str = "flat.ndjson.zst";
             const DATA_FILE_NAME: &str = "data.ndjson.zst";
@@ -97,12 +108,16 @@ async fn main() -> Result<(), Error> {
 
             log::info!("Prepared {} files", paths.len());
 
-            let mut flat_input =
-                archivindex_wxj::lines::io::SnapshotReader::open(input.join(FLAT_FILE_NAME))?
-                    .peekable();
-            let mut data_input =
-                archivindex_wxj::lines::io::SnapshotReader::open(input.join(DATA_FILE_NAME))?
-                    .peekable();
+            let verify_sorted = !no_verify_sorted;
+            let flat_path = input.join(FLAT_FILE_NAME);
+            let data_path = input.join(DATA_FILE_NAME);
+
+            let mut flat_input = archivindex_wxj::lines::io::SnapshotReader::open(&flat_path)?
+                .verify_sorted(verify_sorted)
+                .peekable();
+            let mut data_input = archivindex_wxj::lines::io::SnapshotReader::open(&data_path)?
+                .verify_sorted(verify_sorted)
+                .peekable();
 
             std::fs::create_dir_all(&output)?;
 
@@ -116,16 +131,13 @@ async fn main() -> Result<(), Error> {
                 compression,
             )?;
 
-            for (digest, path, _) in paths {
-                let mut flat_next = flat_input
-                    .peek()
-                    .and_then(|result| result.as_ref().ok())
-                    .map(|snapshot| snapshot.digest);
+            let mut progress = progress::Progress::new(progress);
+
+            for (digest, path, compression_type) in paths {
+                progress.tick();
 
-                let mut data_next = data_input
-                    .peek()
-                    .and_then(|result| result.as_ref().ok())
-                    .map(|snapshot| snapshot.digest);
+                let mut flat_next = peek_digest(&mut flat_input, &flat_path)?;
+                let mut data_next = peek_digest(&mut data_input, &data_path)?;
 
                 while flat_next
                     .map(|flat_digest| flat_digest < digest)
@@ -134,10 +146,7 @@ async fn main() -> Result<(), Error> {
                     // We can unwrap safely because of the peek.
                     let snapshot = flat_input.next().unwrap()?;
                     flat_output.write_snapshot(&snapshot)?;
-                    flat_next = flat_input
-                        .peek()
-                        .and_then(|result| result.as_ref().ok())
-                        .map(|snapshot| snapshot.digest);
+                    flat_next = peek_digest(&mut flat_input, &flat_path)?;
                 }
 
                 while data_next
@@ -147,10 +156,7 @@ async fn main() -> Result<(), Error> {
                     // We can unwrap safely because of the peek.
                     let snapshot = data_input.next().unwrap()?;
                     data_output.write_snapshot(&snapshot)?;
-                    data_next = data_input
-                        .peek()
-                        .and_then(|result| result.as_ref().ok())
-                        .map(|snapshot| snapshot.digest);
+                    data_next = peek_digest(&mut data_input, &data_path)?;
                 }
 
                 if flat_next == Some(digest) {
@@ -162,7 +168,8 @@ async fn main() -> Result<(), Error> {
                     let snapshot = data_input.next().unwrap()?;
                     data_output.write_snapshot(&snapshot)?;
                 } else {
-                    match std::fs::read_to_string(&path).map_err(|error| Error::FileIo(path, error))
+                    match snapshot::read_snapshot(&path, compression_type)
+                        .map_err(|error| Error::FileIo(path, error))
                     {
                         Ok(content) => {
                             let bytes = content.as_bytes();
@@ -189,37 +196,263 @@ async fn main() -> Result<(), Error> {
             }
 
             for snapshot_line in flat_input {
-                flat_output.write_snapshot(&snapshot_line?)?;
+                flat_output.write_snapshot(
+                    &snapshot_line.map_err(|error| input_error(error, &flat_path))?,
+                )?;
             }
 
             for snapshot_line in data_input {
-                data_output.write_snapshot(&snapshot_line?)?;
+                data_output.write_snapshot(
+                    &snapshot_line.map_err(|error| input_error(error, &data_path))?,
+                )?;
             }
 
             flat_output.finish()?;
             data_output.finish()?;
         }
-        Command::TweetIds { input, flat } => {
+        Command::CanonicalUrls { input } => {
             let reader = BufReader::new(zstd::Decoder::new(File::open(&input)?)?);
+            let mut missing_count = 0;
 
             for line in reader.lines() {
                 let line = line?;
 
-                let snapshot = if flat {
-                    serde_json::from_str::<Snapshot<flat::TweetSnapshot>>(&line)?
-                        .map_content(TweetSnapshot::Flat)
+                let snapshot_line = SnapshotLine::parse(&line)?;
+
+                match snapshot_line.canonical_url()? {
+                    Some(url) => {
+                        println!("{},{}", snapshot_line.digest, url);
+                    }
+                    None => {
+                        println!("{},", snapshot_line.digest);
+                        log::error!("No canonical URL: {}", snapshot_line.digest);
+
+                        missing_count += 1;
+                    }
+                }
+            }
+
+            log::info!("{} without canonical URL", missing_count);
+        }
+        Command::DedupByTweet {
+            input,
+            output,
+            compression,
+        } => {
+            let reader = archivindex_wxj::lines::io::SnapshotReader::open(&input)?;
+            let deduped = SnapshotLine::dedup_by_tweet_id(reader)?;
+
+            let mut writer =
+                archivindex_wxj::lines::io::SnapshotWriter::create(&output, compression)?;
+
+            for snapshot_line in &deduped {
+                writer.write_snapshot(snapshot_line)?;
+            }
+
+            writer.finish()?;
+
+            log::info!("{} lines written", deduped.len());
+        }
+        Command::Dedup {
+            input,
+            output,
+            compression,
+        } => {
+            let reader =
+                archivindex_wxj::lines::io::SnapshotReader::open(&input)?.verify_sorted(true);
+
+            let mut writer =
+                archivindex_wxj::lines::io::SnapshotWriter::create(&output, compression)?;
+
+            let counts = archivindex_wxj::lines::io::dedup_snapshots(reader, &mut writer)
+                .map_err(|error| input_error(error, &input))?;
+
+            writer.finish()?;
+
+            for digest in counts.conflicts {
+                log::warn!("Same digest with different content: {}", digest);
+            }
+
+            log::info!(
+                "{} lines written, {} dropped",
+                counts.written,
+                counts.dropped
+            );
+        }
+        Command::UrlTweetMismatch { input } => {
+            let reader = BufReader::new(zstd::Decoder::new(File::open(&input)?)?);
+            let mut mismatch_count = 0;
+
+            for line in reader.lines() {
+                let line = line?;
+
+                let snapshot_line = SnapshotLine::parse(&line)?;
+
+                if let Some((url_tweet_id, tweet_id)) = snapshot_line.url_tweet_id_mismatch()? {
+                    println!("{},{},{}", snapshot_line.digest, url_tweet_id, tweet_id);
+
+                    mismatch_count += 1;
+                }
+            }
+
+            log::info!("{} mismatches", mismatch_count);
+        }
+        Command::Diff {
+            left,
+            right,
+            print_left_only,
+        } => {
+            let diff = Diff::new(
+                archivindex_wxj::lines::io::SnapshotReader::open(&left)?.verify_sorted(true),
+                archivindex_wxj::lines::io::SnapshotReader::open(&right)?.verify_sorted(true),
+            );
+
+            let mut counts = DiffCounts::default();
+
+            for entry in diff {
+                let entry = entry?;
+
+                if print_left_only {
+                    if let DiffEntry::LeftOnly(snapshot_line) = &entry {
+                        println!("{}", snapshot_line.digest);
+                    }
                 } else {
-                    serde_json::from_str::<Snapshot<data::TweetSnapshot>>(&line)?
-                        .map_content(TweetSnapshot::Data)
+                    match &entry {
+                        DiffEntry::LeftOnly(snapshot_line) => {
+                            println!("-{}", snapshot_line.digest);
+                        }
+                        DiffEntry::RightOnly(snapshot_line) => {
+                            println!("+{}", snapshot_line.digest);
+                        }
+                        DiffEntry::Both(snapshot_line, _) if entry.is_modified() => {
+                            println!("~{}", snapshot_line.digest);
+                        }
+                        DiffEntry::Both(_, _) => {}
+                    }
+                }
+
+                counts.add(&entry);
+            }
+
+            log::info!(
+                "{} left only, {} right only, {} common ({} modified)",
+                counts.left_only,
+                counts.right_only,
+                counts.common,
+                counts.modified
+            );
+        }
+        Command::UserCounts { input, top } => {
+            let mut user_counts = UserCounts::default();
+
+            for snapshot_line in archivindex_wxj::lines::io::SnapshotReader::open(&input)? {
+                user_counts.add(&snapshot_line?)?;
+            }
+
+            for (user_id, count) in user_counts.top(top) {
+                println!("{},{}", user_id, count);
+            }
+
+            log::info!("{} lines without author ID", user_counts.unknown);
+        }
+        Command::Stats { input, json } => {
+            let mut files = vec![];
+            let mut total = LineStats::default();
+
+            for path in input {
+                let mut stats = LineStats::default();
+
+                for snapshot_line in archivindex_wxj::lines::io::SnapshotReader::open(&path)? {
+                    stats.add(&snapshot_line?);
+                }
+
+                total.merge(&stats);
+                files.push(FileLineStats { path, stats });
+            }
+
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&LineStatsOutput { files, total })?
+                );
+            } else {
+                for FileLineStats { path, stats } in files {
+                    println!("{}", path.as_os_str().to_string_lossy());
+                    println!("{stats}\n");
+                }
+
+                println!("Total");
+                println!("{total}");
+            }
+        }
+        Command::Explain { input, digest } => {
+            let digest = digest.parse::<Sha1Digest>()?;
+            let reader = BufReader::new(zstd::Decoder::new(File::open(&input)?)?);
+
+            match SnapshotLineExplanation::find(reader.lines(), digest)? {
+                Some((line_number, explanation)) => {
+                    let closing_whitespace = explanation
+                        .closing_whitespace
+                        .iter()
+                        .map(|byte| format!("{byte:#04x}"))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+
+                    println!("Found: line {line_number}");
+                    println!("Stored digest: {}", explanation.stored_digest);
+                    println!("Computed digest: {}", explanation.computed_digest);
+                    println!("Closing whitespace: {closing_whitespace}");
+                    println!("Content length: {}", explanation.content_len);
+
+                    match explanation.content_json_error {
+                        Some(error) => println!("Content JSON: invalid ({error})"),
+                        None => println!("Content JSON: valid"),
+                    }
+                }
+                None => {
+                    println!("Not found");
+                }
+            }
+        }
+        Command::TweetIds {
+            input,
+            urls,
+            x,
+            progress,
+        } => {
+            let mut progress = progress::Progress::new(progress);
+            let file = File::open(&input)?;
+            let len = file.metadata()?.len();
+            let reader = BufReader::new(zstd::Decoder::new(progress.reader(file, len))?);
+
+            for line in reader.lines() {
+                let line = line?;
+                progress.tick();
+
+                let snapshot_line = SnapshotLine::parse(&line)?;
+
+                let Some(kind) = TweetSnapshotKind::from_content(&snapshot_line.content) else {
+                    log::warn!("Unknown content format: {}", snapshot_line.digest);
+                    continue;
                 };
 
+                let snapshot = kind.parse(&snapshot_line.content)?;
                 let metadata =
                     birdsite::model::metadata::tweet::TweetMetadata::from_tweet_snapshot(
-                        &snapshot.content,
+                        &snapshot,
                     )?;
 
-                for tweet in metadata {
-                    println!("{},{}", tweet.user.id, tweet.id);
+                if urls && archivindex_wxj::canonical::canonical_url(&snapshot, x).is_none() {
+                    log::warn!("No canonical URL: {}", snapshot_line.digest);
+                }
+
+                for row in archivindex_wxj::canonical::tweet_id_rows(
+                    &snapshot,
+                    metadata.into_iter().map(|tweet| (tweet.user.id, tweet.id)),
+                    urls,
+                    x,
+                ) {
+                    println!("{row}");
                 }
             }
         }
@@ -228,18 +461,59 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
+/// Peek at the next digest, failing immediately if the next line could not be read.
+fn peek_digest<I: Iterator<Item = Result<SnapshotLine<'static>, archivindex_wxj::lines::Error>>>(
+    input: &mut std::iter::Peekable<I>,
+    path: &Path,
+) -> Result<Option<Sha1Digest>, Error> {
+    match input.peek() {
+        Some(Ok(snapshot_line)) => Ok(Some(snapshot_line.digest)),
+        Some(Err(_)) => match input.next() {
+            Some(Err(error)) => Err(input_error(error, path)),
+            _ => unreachable!(),
+        },
+        None => Ok(None),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FileLineStats {
+    path: PathBuf,
+    #[serde(flatten)]
+    stats: LineStats,
+}
+
+#[derive(serde::Serialize)]
+struct LineStatsOutput {
+    files: Vec<FileLineStats>,
+    total: LineStats,
+}
+
+fn input_error(error: archivindex_wxj::lines::Error, path: &Path) -> Error {
+    match error {
+        archivindex_wxj::lines::Error::Unsorted(digest) => {
+            Error::UnsortedInput(path.to_path_buf(), digest)
+        }
+        other => other.into(),
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("I/O error")]
     Io(#[from] std::io::Error),
     #[error("File I/O error")]
     FileIo(PathBuf, std::io::Error),
+    #[error("Unsorted input in {0:?} at digest {1}")]
+    UnsortedInput(PathBuf, Sha1Digest),
     #[error("CLI argument reading error")]
     Args(#[from] cli_helpers::Error),
     #[error("CSV error")]
     Csv(#[from] csv::Error),
     #[error("JSON error")]
     Json(#[from] serde_json::Error),
+    #[error("Digest error")]
+    Digest(#[from] archivindex_wbm::digest::Error),
     #[error("WBM snapshot storage import error")]
     WbmCas(#[from] archivindex_wbm::cas::import::Error),
     #[error("WXJ line parsing error")]
@@ -262,6 +536,8 @@ enum Command {
     Validate {
         #[clap(long)]
         input: Vec<PathBuf>,
+        #[clap(long)]
+        progress: bool,
     },
     Incomplete {
         #[clap(long)]
@@ -276,11 +552,69 @@ enum Command {
         output: PathBuf,
         #[clap(long, default_value = "14")]
         compression: u16,
+        #[clap(long)]
+        no_verify_sorted: bool,
+        #[clap(long)]
+        progress: bool,
     },
     TweetIds {
         #[clap(long)]
         input: PathBuf,
         #[clap(long)]
-        flat: bool,
+        urls: bool,
+        #[clap(long)]
+        x: bool,
+        #[clap(long)]
+        progress: bool,
+    },
+    CanonicalUrls {
+        #[clap(long)]
+        input: PathBuf,
+    },
+    DedupByTweet {
+        #[clap(long)]
+        input: PathBuf,
+        #[clap(long)]
+        output: PathBuf,
+        #[clap(long, default_value = "14")]
+        compression: u16,
+    },
+    Dedup {
+        #[clap(long)]
+        input: PathBuf,
+        #[clap(long)]
+        output: PathBuf,
+        #[clap(long, default_value = "14")]
+        compression: u16,
+    },
+    UrlTweetMismatch {
+        #[clap(long)]
+        input: PathBuf,
+    },
+    Diff {
+        #[clap(long)]
+        left: PathBuf,
+        #[clap(long)]
+        right: PathBuf,
+        #[clap(long)]
+        print_left_only: bool,
+    },
+    UserCounts {
+        #[clap(long)]
+        input: PathBuf,
+        #[clap(long, default_value = "100")]
+        top: usize,
+    },
+    Stats {
+        #[clap(long)]
+        input: Vec<PathBuf>,
+        #[clap(long)]
+        json: bool,
+    },
+    Explain {
+        #[clap(long)]
+        input: PathBuf,
+        #[clap(long)]
+        digest: String,
     },
 }
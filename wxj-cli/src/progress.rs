@@ -0,0 +1,72 @@
+use std::cell::Cell;
+use std::io::Read;
+use std::rc::Rc;
+
+const LOG_INTERVAL: usize = 100_000;
+
+/// Periodically logs the number of lines or files processed.
+///
+/// If the input is read through a `ProgressReader`, the position in the underlying file is also reported. For compressed
+/// files this is the position in the compressed input, which is still a reasonable estimate of progress.
+pub struct Progress {
+    enabled: bool,
+    count: usize,
+    input: Option<(Rc<Cell<u64>>, u64)>,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            count: 0,
+            input: None,
+        }
+    }
+
+    /// Wrap a reader for an input of the given length, resetting the count.
+    pub fn reader<R: Read>(&mut self, underlying: R, len: u64) -> ProgressReader<R> {
+        let position = Rc::new(Cell::new(0));
+
+        self.count = 0;
+        self.input = Some((position.clone(), len));
+
+        ProgressReader {
+            underlying,
+            position,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.count += 1;
+
+        if self.enabled && self.count.is_multiple_of(LOG_INTERVAL) {
+            match &self.input {
+                Some((position, len)) if *len > 0 => {
+                    log::info!(
+                        "{} processed ({:.1}%)",
+                        self.count,
+                        position.get() as f64 * 100.0 / *len as f64
+                    );
+                }
+                _ => {
+                    log::info!("{} processed", self.count);
+                }
+            }
+        }
+    }
+}
+
+/// Tracks the number of bytes read for `Progress`.
+pub struct ProgressReader<R> {
+    underlying: R,
+    position: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.underlying.read(buf)?;
+        self.position.set(self.position.get() + count as u64);
+
+        Ok(count)
+    }
+}
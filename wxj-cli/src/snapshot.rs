@@ -43,3 +43,16 @@ pub fn snapshot_import<P: AsRef<Path>>(
 
     Ok(result)
 }
+
+/// Read a snapshot file's contents, decompressing them if necessary.
+pub fn read_snapshot<P: AsRef<Path>>(
+    path: P,
+    compression_type: Option<CompressionType>,
+) -> Result<String, std::io::Error> {
+    let file = std::fs::File::open(path)?;
+
+    match compression_type {
+        Some(compression_type) => std::io::read_to_string(compression_type.decoder(file)?),
+        None => std::io::read_to_string(file),
+    }
+}
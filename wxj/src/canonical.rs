@@ -0,0 +1,150 @@
+//! Canonical tweet URLs computed from snapshot contents.
+
+use birdsite::model::wxj::{TweetSnapshot, data, flat};
+
+/// The canonical URL for a snapshot in either format.
+///
+/// Returns `None` for data-format snapshots that do not include the author.
+pub fn canonical_url(snapshot: &TweetSnapshot, use_x: bool) -> Option<String> {
+    match snapshot {
+        TweetSnapshot::Data(snapshot) => data_canonical_url(snapshot, use_x),
+        TweetSnapshot::Flat(snapshot) => Some(flat_canonical_url(snapshot, use_x)),
+    }
+}
+
+pub fn data_canonical_url(snapshot: &data::TweetSnapshot, use_x: bool) -> Option<String> {
+    snapshot.lookup_user(snapshot.data.author_id).map(|user| {
+        format!(
+            "https://{}.com/{}/status/{}",
+            if use_x { "x" } else { "twitter" },
+            user.username,
+            snapshot.data.id
+        )
+    })
+}
+
+pub fn flat_canonical_url(snapshot: &flat::TweetSnapshot, use_x: bool) -> String {
+    format!(
+        "https://{}.com/{}/status/{}",
+        if use_x { "x" } else { "twitter" },
+        snapshot.user.screen_name,
+        snapshot.id
+    )
+}
+
+/// CSV rows of user and tweet IDs for the tweets in a snapshot, with an optional canonical URL column.
+///
+/// Only the snapshot's primary tweet has a canonical URL, so the URL column is empty for other tweets (and for the
+/// primary tweet if no URL can be computed).
+pub fn tweet_id_rows<I: IntoIterator<Item = (u64, u64)>>(
+    snapshot: &TweetSnapshot,
+    user_and_tweet_ids: I,
+    urls: bool,
+    use_x: bool,
+) -> Vec<String> {
+    let primary_tweet_id = match snapshot {
+        TweetSnapshot::Data(snapshot) => snapshot.data.id,
+        TweetSnapshot::Flat(snapshot) => snapshot.id,
+    };
+
+    let url = if urls {
+        canonical_url(snapshot, use_x)
+    } else {
+        None
+    };
+
+    user_and_tweet_ids
+        .into_iter()
+        .map(|(user_id, tweet_id)| {
+            if !urls {
+                format!("{user_id},{tweet_id}")
+            } else if tweet_id == primary_tweet_id
+                && let Some(url) = &url
+            {
+                format!("{user_id},{tweet_id},{url}")
+            } else {
+                format!("{user_id},{tweet_id},")
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::{SnapshotLine, tweet::TweetSnapshotKind};
+
+    fn parse_snapshot(line: &str) -> Result<TweetSnapshot, Box<dyn std::error::Error>> {
+        let snapshot_line = SnapshotLine::parse(line)?;
+        let kind =
+            TweetSnapshotKind::from_content(&snapshot_line.content).ok_or("Expected kind")?;
+
+        Ok(kind.parse(&snapshot_line.content)?)
+    }
+
+    #[test]
+    fn canonical_url_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let line = include_str!("../../examples/wxj/lines-01.ndjson")
+            .split("\n")
+            .next()
+            .ok_or("Expected line")?;
+        let snapshot = parse_snapshot(line)?;
+
+        assert_eq!(
+            canonical_url(&snapshot, false).as_deref(),
+            Some("https://twitter.com/RayMairead/status/1787642853474087122")
+        );
+        assert_eq!(
+            canonical_url(&snapshot, true).as_deref(),
+            Some("https://x.com/RayMairead/status/1787642853474087122")
+        );
+
+        // This snapshot does not include the author.
+        let snapshot =
+            parse_snapshot(include_str!("../../examples/wxj/inferred-url-01.json").trim())?;
+
+        assert_eq!(canonical_url(&snapshot, false), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tweet_id_rows_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let line = include_str!("../../examples/wxj/lines-01.ndjson")
+            .split("\n")
+            .next()
+            .ok_or("Expected line")?;
+        let snapshot = parse_snapshot(line)?;
+        let ids = [(10, 1787642853474087122), (20, 1)];
+
+        assert_eq!(
+            tweet_id_rows(&snapshot, ids, false, false),
+            vec!["10,1787642853474087122", "20,1"]
+        );
+        assert_eq!(
+            tweet_id_rows(&snapshot, ids, true, false),
+            vec![
+                "10,1787642853474087122,https://twitter.com/RayMairead/status/1787642853474087122",
+                "20,1,"
+            ]
+        );
+        assert_eq!(
+            tweet_id_rows(&snapshot, ids, true, true),
+            vec![
+                "10,1787642853474087122,https://x.com/RayMairead/status/1787642853474087122",
+                "20,1,"
+            ]
+        );
+
+        // This snapshot does not include the author, so even the primary tweet has no URL.
+        let snapshot =
+            parse_snapshot(include_str!("../../examples/wxj/inferred-url-01.json").trim())?;
+
+        assert_eq!(
+            tweet_id_rows(&snapshot, [(10, 1919811194589786122)], true, false),
+            vec!["10,1919811194589786122,"]
+        );
+
+        Ok(())
+    }
+}
@@ -1 +1,3 @@
+pub mod canonical;
 pub mod lines;
+pub mod url_paths;
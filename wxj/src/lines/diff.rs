@@ -0,0 +1,245 @@
+//! Streaming comparison of snapshot line files that are sorted by digest.
+
+use super::{Error, SnapshotLine};
+use archivindex_wbm::digest::Sha1Digest;
+use std::iter::Peekable;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiffEntry {
+    LeftOnly(SnapshotLine<'static>),
+    RightOnly(SnapshotLine<'static>),
+    Both(SnapshotLine<'static>, SnapshotLine<'static>),
+}
+
+impl DiffEntry {
+    pub fn digest(&self) -> Sha1Digest {
+        match self {
+            Self::LeftOnly(snapshot_line) => snapshot_line.digest,
+            Self::RightOnly(snapshot_line) => snapshot_line.digest,
+            Self::Both(snapshot_line, _) => snapshot_line.digest,
+        }
+    }
+
+    /// Whether the digest is in both inputs but the lines differ in content, URL, or timestamp.
+    pub fn is_modified(&self) -> bool {
+        match self {
+            Self::Both(left, right) => {
+                left.content != right.content
+                    || left.url != right.url
+                    || left.timestamp != right.timestamp
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DiffCounts {
+    pub left_only: usize,
+    pub right_only: usize,
+    pub common: usize,
+    /// Common digests whose lines differ (these are also included in `common`).
+    pub modified: usize,
+}
+
+impl DiffCounts {
+    pub fn add(&mut self, entry: &DiffEntry) {
+        match entry {
+            DiffEntry::LeftOnly(_) => self.left_only += 1,
+            DiffEntry::RightOnly(_) => self.right_only += 1,
+            DiffEntry::Both(_, _) => {
+                self.common += 1;
+
+                if entry.is_modified() {
+                    self.modified += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Merge-join two digest-sorted sequences of snapshot lines.
+///
+/// Neither input is buffered beyond a single line, but the output is only meaningful if both are sorted (see
+/// `SnapshotReader::verify_sorted`).
+pub struct Diff<L: Iterator, R: Iterator> {
+    left: Peekable<L>,
+    right: Peekable<R>,
+}
+
+impl<L, R> Diff<L, R>
+where
+    L: Iterator<Item = Result<SnapshotLine<'static>, Error>>,
+    R: Iterator<Item = Result<SnapshotLine<'static>, Error>>,
+{
+    pub fn new(left: L, right: R) -> Self {
+        Self {
+            left: left.peekable(),
+            right: right.peekable(),
+        }
+    }
+}
+
+impl<L, R> Iterator for Diff<L, R>
+where
+    L: Iterator<Item = Result<SnapshotLine<'static>, Error>>,
+    R: Iterator<Item = Result<SnapshotLine<'static>, Error>>,
+{
+    type Item = Result<DiffEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let left_digest = match self.left.peek() {
+            Some(Ok(snapshot_line)) => Some(snapshot_line.digest),
+            Some(Err(_)) => {
+                return self
+                    .left
+                    .next()
+                    .map(|result| result.map(DiffEntry::LeftOnly));
+            }
+            None => None,
+        };
+
+        let right_digest = match self.right.peek() {
+            Some(Ok(snapshot_line)) => Some(snapshot_line.digest),
+            Some(Err(_)) => {
+                return self
+                    .right
+                    .next()
+                    .map(|result| result.map(DiffEntry::RightOnly));
+            }
+            None => None,
+        };
+
+        match (left_digest, right_digest) {
+            (Some(left_digest), Some(right_digest)) if left_digest == right_digest => {
+                // We can unwrap safely because of the peeks.
+                let left = self.left.next().unwrap();
+                let right = self.right.next().unwrap();
+
+                Some(left.and_then(|left| right.map(|right| DiffEntry::Both(left, right))))
+            }
+            (Some(left_digest), Some(right_digest)) if left_digest > right_digest => self
+                .right
+                .next()
+                .map(|result| result.map(DiffEntry::RightOnly)),
+            (Some(_), _) => self
+                .left
+                .next()
+                .map(|result| result.map(DiffEntry::LeftOnly)),
+            (None, Some(_)) => self
+                .right
+                .next()
+                .map(|result| result.map(DiffEntry::RightOnly)),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::io::{SnapshotReader, SnapshotWriter};
+
+    fn write_lines(digests: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        let mut output = vec![];
+        let mut writer = SnapshotWriter::new(&mut output);
+
+        for digest in digests {
+            writer.write(Sha1Digest([*digest; 20]), "{}\r\r\n".as_bytes())?;
+        }
+
+        Ok(output)
+    }
+
+    #[test]
+    fn diff_counts() -> Result<(), Box<dyn std::error::Error>> {
+        let left = write_lines(&[1, 2, 3, 6])?;
+        let right = write_lines(&[2, 3, 4, 5])?;
+
+        let diff = Diff::new(
+            SnapshotReader::new(left.as_slice()).verify_sorted(true),
+            SnapshotReader::new(right.as_slice()).verify_sorted(true),
+        );
+
+        let mut counts = DiffCounts::default();
+        let mut left_only = vec![];
+
+        for entry in diff {
+            let entry = entry?;
+
+            if let DiffEntry::LeftOnly(snapshot_line) = &entry {
+                left_only.push(snapshot_line.digest);
+            }
+
+            counts.add(&entry);
+        }
+
+        assert_eq!(
+            counts,
+            DiffCounts {
+                left_only: 2,
+                right_only: 2,
+                common: 2,
+                modified: 0
+            }
+        );
+        assert_eq!(left_only, vec![Sha1Digest([1; 20]), Sha1Digest([6; 20])]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_modified() -> Result<(), Box<dyn std::error::Error>> {
+        let left = write_lines(&[1, 2, 3])?;
+        let mut right = vec![];
+        let mut writer = SnapshotWriter::new(&mut right);
+
+        writer.write(Sha1Digest([2; 20]), "{}\r\r\n".as_bytes())?;
+        writer.write_with_metadata(
+            Sha1Digest([3; 20]),
+            "{}\r\r\n".as_bytes(),
+            None,
+            Some("https://twitter.com/jack/status/20"),
+            None,
+        )?;
+        writer.write(Sha1Digest([4; 20]), "{}\r\r\n".as_bytes())?;
+
+        let diff = Diff::new(
+            SnapshotReader::new(left.as_slice()).verify_sorted(true),
+            SnapshotReader::new(right.as_slice()).verify_sorted(true),
+        );
+
+        let mut counts = DiffCounts::default();
+        let mut removed = vec![];
+        let mut added = vec![];
+        let mut modified = vec![];
+
+        for entry in diff {
+            let entry = entry?;
+
+            match &entry {
+                DiffEntry::LeftOnly(snapshot_line) => removed.push(snapshot_line.digest),
+                DiffEntry::RightOnly(snapshot_line) => added.push(snapshot_line.digest),
+                DiffEntry::Both(_, _) if entry.is_modified() => modified.push(entry.digest()),
+                DiffEntry::Both(_, _) => {}
+            }
+
+            counts.add(&entry);
+        }
+
+        assert_eq!(
+            counts,
+            DiffCounts {
+                left_only: 1,
+                right_only: 1,
+                common: 2,
+                modified: 1
+            }
+        );
+        assert_eq!(removed, vec![Sha1Digest([1; 20])]);
+        assert_eq!(added, vec![Sha1Digest([4; 20])]);
+        assert_eq!(modified, vec![Sha1Digest([3; 20])]);
+
+        Ok(())
+    }
+}
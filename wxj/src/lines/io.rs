@@ -1,63 +1,246 @@
-use super::SnapshotLine;
-use archivindex_wbm::digest::Sha1Digest;
+use super::{SnapshotFileHeader, SnapshotLine};
+use archivindex_wbm::{
+    cas::import::CompressionType,
+    digest::{Digest, Sha1Digest},
+    timestamp::Timestamp,
+};
+use sha1::{Digest as _, Sha1};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Lines, Read, Write};
-use std::path::Path;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Read, Write};
+use std::path::{Path, PathBuf};
 
 pub struct SnapshotReader<R> {
     underlying: Lines<BufReader<R>>,
+    header: Option<SnapshotFileHeader>,
+    // The first line, if it isn't a header.
+    pending: Option<Result<String, super::Error>>,
+    verify_sorted: bool,
+    last_digest: Option<Sha1Digest>,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    /// Create a reader, immediately reading the first line to check for a header.
+    pub fn new(underlying: R) -> Self {
+        let mut underlying = BufReader::new(underlying).lines();
+        let mut header = None;
+
+        let pending = match underlying.next() {
+            Some(Ok(line)) => match SnapshotFileHeader::parse_line(&line) {
+                Some(Ok(parsed_header)) => {
+                    header = Some(parsed_header);
+                    None
+                }
+                Some(Err(error)) => Some(Err(error)),
+                None => Some(Ok(line)),
+            },
+            Some(Err(error)) => Some(Err(super::Error::from(error))),
+            None => None,
+        };
+
+        Self {
+            underlying,
+            header,
+            pending,
+            verify_sorted: false,
+            last_digest: None,
+        }
+    }
+
+    /// Fail with `Error::Unsorted` if the digests are not non-decreasing.
+    pub fn verify_sorted(mut self, verify_sorted: bool) -> Self {
+        self.verify_sorted = verify_sorted;
+        self
+    }
+
+    /// The file's header, if it has one.
+    pub fn header(&self) -> Option<&SnapshotFileHeader> {
+        self.header.as_ref()
+    }
 }
 
 impl<'a> SnapshotReader<zstd::Decoder<'a, BufReader<File>>> {
     pub fn open<P: AsRef<Path>>(input: P) -> Result<Self, std::io::Error> {
-        Ok(Self {
-            underlying: BufReader::new(zstd::Decoder::new(File::open(input)?)?).lines(),
-        })
+        Ok(Self::new(zstd::Decoder::new(File::open(input)?)?))
+    }
+}
+
+impl SnapshotReader<flate2::read::MultiGzDecoder<File>> {
+    pub fn open_gzip<P: AsRef<Path>>(input: P) -> Result<Self, std::io::Error> {
+        Ok(Self::new(flate2::read::MultiGzDecoder::new(File::open(
+            input,
+        )?)))
+    }
+}
+
+impl SnapshotReader<Box<dyn Read>> {
+    /// Open a file that is compressed in the given format, or uncompressed if `compression_type` is `None`.
+    pub fn open_with_compression<P: AsRef<Path>>(
+        input: P,
+        compression_type: Option<CompressionType>,
+    ) -> Result<Self, std::io::Error> {
+        let file = File::open(input)?;
+
+        let underlying: Box<dyn Read> = match compression_type {
+            Some(compression_type) => compression_type.decoder(file)?,
+            None => Box::new(file),
+        };
+
+        Ok(Self::new(underlying))
     }
 }
 
 impl<R: Read> Iterator for SnapshotReader<R> {
     type Item = Result<SnapshotLine<'static>, super::Error>;
 
+    /// Lines that use the default closing whitespace declared in a header are returned with explicit closing
+    /// whitespace, so that they validate as usual.
     fn next(&mut self) -> Option<Self::Item> {
-        self.underlying.next().map(|result| {
-            result
-                .map_err(super::Error::from)
-                .and_then(|line| SnapshotLine::parse(&line).map(|snapshot| snapshot.into_owned()))
-        })
+        self.pending
+            .take()
+            .or_else(|| {
+                self.underlying
+                    .next()
+                    .map(|result| result.map_err(super::Error::from))
+            })
+            .map(|result| {
+                let mut snapshot_line = result.and_then(|line| {
+                    SnapshotLine::parse(&line).map(|snapshot| snapshot.into_owned())
+                })?;
+
+                if let Some(header) = &self.header {
+                    header.resolve(&mut snapshot_line);
+                }
+
+                if self.verify_sorted {
+                    if self
+                        .last_digest
+                        .is_some_and(|last_digest| snapshot_line.digest < last_digest)
+                    {
+                        return Err(super::Error::Unsorted(snapshot_line.digest));
+                    }
+
+                    self.last_digest = Some(snapshot_line.digest);
+                }
+
+                Ok(snapshot_line)
+            })
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WriteOutcome {
+    Written,
+    /// The digest is the same as the last one written.
+    Duplicate,
+    DigestMismatch {
+        found: Sha1Digest,
+    },
+}
+
 pub struct SnapshotWriter<W> {
     last_written: Option<Sha1Digest>,
+    header: Option<SnapshotFileHeader>,
     underlying: W,
 }
 
 impl<W: Write> SnapshotWriter<W> {
+    pub fn new(underlying: W) -> Self {
+        Self {
+            last_written: None,
+            header: None,
+            underlying,
+        }
+    }
+
+    /// Write a header line, which must be done before any other lines are written.
+    ///
+    /// Subsequent lines will omit their closing whitespace if it is the default declared in the header.
+    pub fn write_header(&mut self, header: SnapshotFileHeader) -> Result<(), std::io::Error> {
+        if self.last_written.is_some() || self.header.is_some() {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "header must be written first",
+            ))
+        } else {
+            serde_json::to_writer(&mut self.underlying, &header)?;
+            writeln!(self.underlying)?;
+            self.header = Some(header);
+
+            Ok(())
+        }
+    }
+
+    fn write_line(&mut self, snapshot_line: &SnapshotLine) -> Result<(), std::io::Error> {
+        match &self.header {
+            Some(header) => writeln!(self.underlying, "{}", header.encode(snapshot_line)),
+            None => writeln!(self.underlying, "{}", snapshot_line),
+        }
+    }
+
     pub fn write<R: Read>(
         &mut self,
         digest: Sha1Digest,
         reader: R,
+    ) -> Result<bool, std::io::Error> {
+        self.write_with_metadata(digest, reader, None, None, None)
+    }
+
+    /// Write a line with the given metadata fields populated.
+    pub fn write_with_metadata<R: Read>(
+        &mut self,
+        digest: Sha1Digest,
+        reader: R,
+        timestamp: Option<Timestamp>,
+        url: Option<&str>,
+        expected_digest: Option<Sha1Digest>,
     ) -> Result<bool, std::io::Error> {
         if Some(digest) == self.last_written {
             Ok(false)
         } else {
             let content = std::io::read_to_string(reader)?;
-            let snapshot_line = SnapshotLine::new(digest, &content);
+            let mut snapshot_line = SnapshotLine::new(digest, &content);
 
-            writeln!(self.underlying, "{}", snapshot_line)?;
+            snapshot_line.expected_digest = expected_digest.map(Digest::from);
+            snapshot_line.timestamp = timestamp;
+            snapshot_line.url = url.map(Cow::from);
+
+            self.write_line(&snapshot_line)?;
             self.last_written = Some(digest);
 
             Ok(true)
         }
     }
 
+    /// Compute the digest of the content and write it only if it matches the expected digest.
+    ///
+    /// The content must include its closing whitespace. As with `write`, consecutive values with the same digest are
+    /// ignored.
+    pub fn write_verified<R: Read>(
+        &mut self,
+        reader: R,
+        expected: Sha1Digest,
+    ) -> Result<WriteOutcome, std::io::Error> {
+        let content = std::io::read_to_string(reader)?;
+        let found = Sha1Digest(Sha1::digest(content.as_bytes()).into());
+
+        if !found.ct_eq(&expected) {
+            Ok(WriteOutcome::DigestMismatch { found })
+        } else if self.write(found, content.as_bytes())? {
+            Ok(WriteOutcome::Written)
+        } else {
+            Ok(WriteOutcome::Duplicate)
+        }
+    }
+
     /// Ignores consecutive values with the same digest.
     pub fn write_snapshot(&mut self, snapshot_line: &SnapshotLine) -> Result<bool, std::io::Error> {
         if Some(snapshot_line.digest) == self.last_written {
             Ok(false)
         } else {
-            writeln!(self.underlying, "{}", snapshot_line)?;
+            self.write_line(snapshot_line)?;
             self.last_written = Some(snapshot_line.digest);
 
             Ok(true)
@@ -70,13 +253,565 @@ impl<'a> SnapshotWriter<zstd::Encoder<'a, File>> {
         output: P,
         compression_level: u16,
     ) -> Result<Self, std::io::Error> {
-        Ok(Self {
-            last_written: None,
-            underlying: zstd::Encoder::new(File::create_new(output)?, compression_level as i32)?,
-        })
+        Ok(Self::new(zstd::Encoder::new(
+            File::create_new(output)?,
+            compression_level as i32,
+        )?))
     }
 
     pub fn finish(self) -> Result<File, std::io::Error> {
         self.underlying.finish()
     }
 }
+
+impl SnapshotWriter<flate2::write::GzEncoder<File>> {
+    pub fn create_gzip<P: AsRef<Path>>(
+        output: P,
+        compression_level: u32,
+    ) -> Result<Self, std::io::Error> {
+        Ok(Self::new(flate2::write::GzEncoder::new(
+            File::create_new(output)?,
+            flate2::Compression::new(compression_level),
+        )))
+    }
+
+    pub fn finish(self) -> Result<File, std::io::Error> {
+        self.underlying.finish()
+    }
+}
+
+/// Writes snapshot lines to multiple outputs, routing each by the first byte of its digest.
+///
+/// Each shard ignores consecutive values with the same digest, as with `SnapshotWriter`.
+pub struct ShardedSnapshotWriter<W> {
+    shards: Vec<SnapshotWriter<W>>,
+}
+
+impl<W: Write> ShardedSnapshotWriter<W> {
+    pub fn new(underlying: Vec<W>) -> Result<Self, std::io::Error> {
+        if underlying.is_empty() {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "at least one shard is required",
+            ))
+        } else {
+            Ok(Self {
+                shards: underlying.into_iter().map(SnapshotWriter::new).collect(),
+            })
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn shard(&self, digest: Sha1Digest) -> usize {
+        digest.0[0] as usize % self.shards.len()
+    }
+
+    pub fn write_snapshot(&mut self, snapshot_line: &SnapshotLine) -> Result<bool, std::io::Error> {
+        let shard = self.shard(snapshot_line.digest);
+
+        self.shards[shard].write_snapshot(snapshot_line)
+    }
+}
+
+impl<'a> ShardedSnapshotWriter<zstd::Encoder<'a, File>> {
+    pub fn create<P: AsRef<Path>>(
+        outputs: &[P],
+        compression_level: u16,
+    ) -> Result<Self, std::io::Error> {
+        let underlying = outputs
+            .iter()
+            .map(|output| zstd::Encoder::new(File::create_new(output)?, compression_level as i32))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::new(underlying)
+    }
+
+    pub fn finish(self) -> Result<Vec<File>, std::io::Error> {
+        self.shards
+            .into_iter()
+            .map(SnapshotWriter::finish)
+            .collect()
+    }
+}
+
+/// The result of `dedup_snapshots`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DedupCounts {
+    pub written: usize,
+    pub dropped: usize,
+    /// Digests of dropped lines whose content differs from the line that was kept, which indicates corruption.
+    pub conflicts: Vec<Sha1Digest>,
+}
+
+/// Copy snapshot lines to a writer, dropping consecutive lines with the same digest.
+///
+/// The input should be sorted by digest, since only consecutive duplicates are dropped.
+pub fn dedup_snapshots<
+    I: IntoIterator<Item = Result<SnapshotLine<'static>, super::Error>>,
+    W: Write,
+>(
+    lines: I,
+    writer: &mut SnapshotWriter<W>,
+) -> Result<DedupCounts, super::Error> {
+    let mut counts = DedupCounts::default();
+    let mut last_written: Option<SnapshotLine<'static>> = None;
+
+    for snapshot_line in lines {
+        let snapshot_line = snapshot_line?;
+
+        if writer.write_snapshot(&snapshot_line)? {
+            counts.written += 1;
+            last_written = Some(snapshot_line);
+        } else {
+            counts.dropped += 1;
+
+            if last_written.as_ref().is_some_and(|last_written| {
+                last_written.content != snapshot_line.content
+                    || last_written.closing_whitespace_bytes()
+                        != snapshot_line.closing_whitespace_bytes()
+            }) {
+                counts.conflicts.push(snapshot_line.digest);
+            }
+        }
+    }
+
+    Ok(counts)
+}
+
+/// The total content size at which a sorted run is written to disk in `sort_snapshots`.
+const SORT_RUN_BYTES: usize = 256 * 1024 * 1024;
+
+/// Sort the lines of one or more snapshot files by digest, writing the result to a new file.
+///
+/// This is an external merge sort: sorted runs are spilled to temporary files, so the inputs may be larger than
+/// memory. As with `SnapshotWriter`, only the first of any lines with the same digest is written. Returns the number of
+/// lines written.
+pub fn sort_snapshots<P: AsRef<Path>>(
+    inputs: &[P],
+    output: P,
+    compression: u16,
+) -> Result<usize, super::Error> {
+    sort_snapshots_with_run_bytes(inputs, output, compression, SORT_RUN_BYTES)
+}
+
+fn sort_snapshots_with_run_bytes<P: AsRef<Path>>(
+    inputs: &[P],
+    output: P,
+    compression: u16,
+    run_bytes: usize,
+) -> Result<usize, super::Error> {
+    let directory = tempfile::tempdir()?;
+    let mut run_paths = vec![];
+    let mut run = vec![];
+    let mut run_size = 0;
+
+    for input in inputs {
+        for snapshot_line in SnapshotReader::open(input)? {
+            let snapshot_line = snapshot_line?;
+
+            run_size += snapshot_line.content.len();
+            run.push(snapshot_line);
+
+            if run_size >= run_bytes {
+                run_paths.push(write_sorted_run(
+                    directory.path(),
+                    run_paths.len(),
+                    &mut run,
+                )?);
+                run_size = 0;
+            }
+        }
+    }
+
+    if !run.is_empty() {
+        run_paths.push(write_sorted_run(
+            directory.path(),
+            run_paths.len(),
+            &mut run,
+        )?);
+    }
+
+    let mut readers = run_paths
+        .iter()
+        .map(|path| File::open(path).map(SnapshotReader::new))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut heap = BinaryHeap::with_capacity(readers.len());
+    let mut pending = Vec::with_capacity(readers.len());
+
+    for (i, reader) in readers.iter_mut().enumerate() {
+        let next = reader.next().transpose()?;
+
+        if let Some(snapshot_line) = &next {
+            heap.push(Reverse((snapshot_line.digest, i)));
+        }
+
+        pending.push(next);
+    }
+
+    let mut writer = SnapshotWriter::create(output, compression)?;
+    let mut count = 0;
+
+    // Ties are broken by run index, so the earliest line with a given digest is the one that is written.
+    while let Some(Reverse((_, i))) = heap.pop() {
+        if let Some(snapshot_line) = pending[i].take()
+            && writer.write_snapshot(&snapshot_line)?
+        {
+            count += 1;
+        }
+
+        pending[i] = readers[i].next().transpose()?;
+
+        if let Some(snapshot_line) = &pending[i] {
+            heap.push(Reverse((snapshot_line.digest, i)));
+        }
+    }
+
+    writer.finish()?;
+
+    Ok(count)
+}
+
+fn write_sorted_run(
+    directory: &Path,
+    index: usize,
+    run: &mut Vec<SnapshotLine<'static>>,
+) -> Result<PathBuf, std::io::Error> {
+    // The sort is stable, so duplicates keep their input order.
+    run.sort_by_key(|snapshot_line| snapshot_line.digest);
+
+    let path = directory.join(format!("run-{index}.ndjson"));
+    let mut writer = SnapshotWriter::new(BufWriter::new(File::create_new(&path)?));
+
+    for snapshot_line in run.drain(..) {
+        writer.write_snapshot(&snapshot_line)?;
+    }
+
+    writer.underlying.flush()?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archivindex_wbm::testing::{sha1_digest, snapshot_content};
+
+    #[test]
+    fn write_with_metadata() -> Result<(), Box<dyn std::error::Error>> {
+        let (digest, content) = snapshot_content(1);
+        let expected_digest: Sha1Digest = "ZHYT52YPEOCHJD5FZINSDYXGQZI22WJ4".parse()?;
+        let timestamp: Timestamp = "20160508215503".parse()?;
+        let url = "https://twitter.com/jack/status/1";
+
+        let mut writer = SnapshotWriter::new(vec![]);
+
+        assert!(writer.write_with_metadata(
+            digest,
+            content.as_bytes(),
+            Some(timestamp),
+            Some(url),
+            Some(expected_digest),
+        )?);
+
+        let output = String::from_utf8(writer.underlying)?;
+        let snapshot_line = SnapshotLine::parse(output.trim_end())?;
+
+        assert_eq!(snapshot_line.digest, digest);
+        assert_eq!(
+            snapshot_line.expected_digest,
+            Some(Digest::Valid(expected_digest))
+        );
+        assert_eq!(snapshot_line.timestamp, Some(timestamp));
+        assert_eq!(snapshot_line.url.as_deref(), Some(url));
+        assert_eq!(snapshot_line.validate(&mut Default::default()), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_verified() -> Result<(), Box<dyn std::error::Error>> {
+        let (digest, content) = snapshot_content(1);
+        let (other_digest, other_content) = snapshot_content(2);
+
+        let mut writer = SnapshotWriter::new(vec![]);
+
+        assert_eq!(
+            writer.write_verified(content.as_bytes(), digest)?,
+            WriteOutcome::Written
+        );
+        assert_eq!(
+            writer.write_verified(content.as_bytes(), digest)?,
+            WriteOutcome::Duplicate
+        );
+        assert_eq!(
+            writer.write_verified(other_content.as_bytes(), digest)?,
+            WriteOutcome::DigestMismatch {
+                found: other_digest
+            }
+        );
+
+        let lines =
+            SnapshotReader::new(writer.underlying.as_slice()).collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].digest, digest);
+        assert_eq!(lines[0].validate(&mut Default::default()), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn header_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let header = SnapshotFileHeader::new("\n")?;
+        let contents = [
+            "{\"data\":{\"id\":\"1\"}}\n",
+            "{\"data\":{\"id\":\"2\"}}\r\r\n",
+            "{\"data\":{\"id\":\"3\"}}\r\n",
+        ];
+        let mut digests = vec![];
+
+        let mut writer = SnapshotWriter::new(vec![]);
+        writer.write_header(header.clone())?;
+
+        let mut contents_by_digest = contents
+            .iter()
+            .map(|content| (sha1_digest(content), content))
+            .collect::<Vec<_>>();
+        contents_by_digest.sort();
+
+        for (digest, content) in contents_by_digest {
+            writer.write(digest, content.as_bytes())?;
+            digests.push(digest);
+        }
+
+        assert!(writer.write_header(header.clone()).is_err());
+
+        let output = String::from_utf8(writer.underlying)?;
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(
+            lines[0],
+            "{\"__archivindex_version\":1,\"default_closing_whitespace\":\"\\n\"}"
+        );
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| line.contains("\"closing_whitespace\""))
+                .count(),
+            2
+        );
+
+        let reader = SnapshotReader::new(output.as_bytes());
+
+        assert_eq!(reader.header(), Some(&header));
+
+        let snapshot_lines = reader.collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(
+            snapshot_lines
+                .iter()
+                .map(|snapshot_line| snapshot_line.digest)
+                .collect::<Vec<_>>(),
+            digests
+        );
+
+        for snapshot_line in snapshot_lines {
+            assert_eq!(snapshot_line.validate(&mut Default::default()), Ok(()));
+        }
+
+        let validation =
+            SnapshotLine::validate_lines(std::io::BufReader::new(output.as_bytes()).lines())?;
+
+        assert!(validation.is_successful());
+        assert_eq!(validation.valid_count, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_verify_sorted() -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = SnapshotWriter::new(vec![]);
+
+        for digest in [Sha1Digest::MIN, Sha1Digest::MAX, Sha1Digest([1; 20])] {
+            writer.write(digest, "{}\r\r\n".as_bytes())?;
+        }
+
+        let input = writer.underlying;
+
+        let unverified = SnapshotReader::new(input.as_slice()).collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(unverified.len(), 3);
+
+        let verified = SnapshotReader::new(input.as_slice())
+            .verify_sorted(true)
+            .collect::<Result<Vec<_>, _>>();
+
+        assert!(matches!(
+            verified,
+            Err(crate::lines::Error::Unsorted(digest)) if digest == Sha1Digest([1; 20])
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn gzip_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("snapshots.ndjson.gz");
+
+        let mut digests = (0..10)
+            .map(|i| Sha1Digest([i * 11; 20]))
+            .collect::<Vec<_>>();
+        digests.sort();
+
+        let mut writer = SnapshotWriter::create_gzip(&path, 6)?;
+
+        for digest in &digests {
+            writer.write(*digest, "{}\r\r\n".as_bytes())?;
+        }
+
+        writer.finish()?;
+
+        let read_digests = SnapshotReader::open_gzip(&path)?
+            .verify_sorted(true)
+            .map(|snapshot_line| snapshot_line.map(|snapshot_line| snapshot_line.digest))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(read_digests, digests);
+
+        let read_digests =
+            SnapshotReader::open_with_compression(&path, Some(CompressionType::Gzip))?
+                .map(|snapshot_line| snapshot_line.map(|snapshot_line| snapshot_line.digest))
+                .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(read_digests, digests);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sort_unsorted_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+        let directory = tempfile::tempdir()?;
+        let inputs = [
+            directory.path().join("input-1.ndjson.zst"),
+            directory.path().join("input-2.ndjson.zst"),
+        ];
+        let output = directory.path().join("output.ndjson.zst");
+
+        let contents = (0..40).map(snapshot_content).collect::<Vec<_>>();
+
+        // The second input repeats every third line of the first.
+        for (i, input) in inputs.iter().enumerate() {
+            let mut writer = SnapshotWriter::create(input, 3)?;
+
+            for (digest, content) in contents
+                .iter()
+                .skip(i * 20)
+                .take(20)
+                .chain(contents.iter().step_by(3))
+            {
+                writer.write(*digest, content.as_bytes())?;
+            }
+
+            writer.finish()?;
+        }
+
+        // Use a small run size so that the merge is exercised.
+        let count = super::sort_snapshots_with_run_bytes(&inputs, output.clone(), 3, 256)?;
+
+        assert_eq!(count, contents.len());
+
+        let snapshot_lines = SnapshotReader::open(&output)?
+            .verify_sorted(true)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(snapshot_lines.len(), contents.len());
+
+        let validation = SnapshotLine::validate_lines(
+            BufReader::new(zstd::Decoder::new(File::open(&output)?)?).lines(),
+        )?;
+
+        assert!(validation.is_successful());
+        assert_eq!(validation.valid_count, contents.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dedup_snapshots_conflicts() -> Result<(), Box<dyn std::error::Error>> {
+        let (digest, content) = snapshot_content(1);
+        let (other_digest, other_content) = snapshot_content(2);
+
+        let lines = [
+            SnapshotLine::new(digest, &content),
+            SnapshotLine::new(digest, &content),
+            SnapshotLine::new(other_digest, &other_content),
+            // A corrupted line with a duplicate digest.
+            SnapshotLine::new(other_digest, &content),
+        ];
+
+        let mut input = vec![];
+
+        for snapshot_line in &lines {
+            writeln!(input, "{snapshot_line}")?;
+        }
+
+        let mut writer = SnapshotWriter::new(vec![]);
+        let counts = dedup_snapshots(SnapshotReader::new(input.as_slice()), &mut writer)?;
+
+        assert_eq!(
+            counts,
+            DedupCounts {
+                written: 2,
+                dropped: 2,
+                conflicts: vec![other_digest]
+            }
+        );
+
+        let output =
+            SnapshotReader::new(writer.underlying.as_slice()).collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(output, vec![lines[0].clone(), lines[2].clone()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sharded_write() -> Result<(), Box<dyn std::error::Error>> {
+        let digests = (0..30).map(|i| Sha1Digest([i * 7; 20])).collect::<Vec<_>>();
+
+        let mut writer = ShardedSnapshotWriter::new(vec![vec![], vec![], vec![]])?;
+
+        for digest in &digests {
+            let content = "{}\r\r\n";
+            let snapshot_line = SnapshotLine::new(*digest, content);
+
+            assert!(writer.write_snapshot(&snapshot_line)?);
+            assert!(!writer.write_snapshot(&snapshot_line)?);
+        }
+
+        let mut union = vec![];
+
+        for (i, shard) in writer.shards.into_iter().enumerate() {
+            for snapshot_line in
+                SnapshotReader::new(shard.underlying.as_slice()).verify_sorted(true)
+            {
+                let digest = snapshot_line?.digest;
+
+                assert_eq!(digest.0[0] as usize % 3, i);
+
+                union.push(digest);
+            }
+        }
+
+        union.sort();
+
+        assert_eq!(union, digests);
+
+        Ok(())
+    }
+}
@@ -1,8 +1,14 @@
-use archivindex_wbm::{digest::Sha1Digest, timestamp::Timestamp};
-use sha1::{Digest, Sha1};
+use archivindex_wbm::{
+    digest::{Digest, Sha1Digest},
+    timestamp::Timestamp,
+};
+use sha1::{Digest as _, Sha1};
 use std::borrow::Cow;
 
+pub mod diff;
 pub mod io;
+pub mod stats;
+pub mod tweet;
 
 const DEFAULT_CLOSING_WHITESPACE: [u8; 3] = [b'\r', b'\r', b'\n'];
 
@@ -10,16 +16,23 @@ const DEFAULT_CLOSING_WHITESPACE: [u8; 3] = [b'\r', b'\r', b'\n'];
 pub enum Error {
     #[error("I/O error")]
     Io(#[from] std::io::Error),
-    #[error("Invalid line")]
-    InvalidLine,
+    #[error("Invalid line at offset {offset}: {reason}")]
+    InvalidLine { offset: usize, reason: &'static str },
     #[error("Invalid closing whitespace")]
     InvalidClosingWhitespace(String),
+    #[error("JSON error")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid ID")]
+    InvalidId(String),
+    #[error("Unsorted input at digest {0}")]
+    Unsorted(Sha1Digest),
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Snapshot<'a, S> {
     pub digest: Sha1Digest,
-    pub expected_digest: Option<Sha1Digest>,
+    #[serde(borrow)]
+    pub expected_digest: Option<Digest<'a>>,
     #[serde(
         with = "closing_whitespace",
         default,
@@ -44,10 +57,47 @@ impl<'a, S> Snapshot<'a, S> {
     }
 }
 
+impl<'a, S: serde::Serialize> Snapshot<'a, S> {
+    /// Serialize to the line format used by `SnapshotLine`.
+    ///
+    /// The content is serialized compactly, so the line only matches the original if the content type preserves field
+    /// order and formatting. Fails if the content can't be serialized as JSON (e.g. a map with non-string keys).
+    pub fn to_line_string(&self) -> Result<String, serde_json::Error> {
+        let content = serde_json::to_string(&self.content)?;
+
+        let snapshot_line = SnapshotLine {
+            digest: self.digest,
+            expected_digest: self.expected_digest.clone(),
+            closing_whitespace: self.closing_whitespace.clone(),
+            timestamp: self.timestamp,
+            url: self.url.clone(),
+            content: content.into(),
+        };
+
+        Ok(snapshot_line.to_string())
+    }
+}
+
+/// Determines how closing whitespace is handled when computing a digest for a snapshot line.
+///
+/// Different crawls may terminate the same content differently, so the non-raw policies are useful for matching
+/// snapshots across crawls.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DigestPolicy {
+    /// Use the closing whitespace exactly as captured.
+    #[default]
+    Raw,
+    /// Replace any trailing whitespace with the default closing whitespace (`\r\r\n`).
+    NormalizeTrailingWhitespace,
+    /// Remove all trailing whitespace.
+    StripAllTrailingWhitespace,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SnapshotLine<'a> {
     pub digest: Sha1Digest,
-    pub expected_digest: Option<Sha1Digest>,
+    /// The digest listed in the CDX index, which may not be a valid SHA-1 digest.
+    pub expected_digest: Option<Digest<'a>>,
     pub closing_whitespace: Option<Vec<char>>,
     pub timestamp: Option<Timestamp>,
     pub url: Option<Cow<'a, str>>,
@@ -58,8 +108,15 @@ impl<'a> std::fmt::Display for SnapshotLine<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{{\"{}\":\"{}\",", DIGEST_KEY, self.digest)?;
 
-        if let Some(expected_digest) = self.expected_digest {
-            write!(f, "\"{EXPECTED_DIGEST_KEY}\":\"{expected_digest}\",")?;
+        if let Some(expected_digest) = &self.expected_digest {
+            write!(f, "\"{EXPECTED_DIGEST_KEY}\":\"")?;
+
+            match expected_digest {
+                Digest::Valid(digest) => write!(f, "{digest}")?,
+                Digest::Invalid(value) => write_json_escaped(f, value)?,
+            }
+
+            f.write_str("\",")?;
         }
 
         if let Some(closing_whitespace) = &self.closing_whitespace {
@@ -81,15 +138,107 @@ impl<'a> std::fmt::Display for SnapshotLine<'a> {
         }
 
         if let Some(url) = &self.url {
-            write!(f, "\"{URL_KEY}\":\"{url}\",")?;
+            write!(f, "\"{URL_KEY}\":\"")?;
+            write_json_escaped(f, url)?;
+            f.write_str("\",")?;
         }
 
         write!(f, "\"content\":{}}}", self.content)
     }
 }
 
+/// Write a value as the contents of a JSON string literal (without the surrounding quotes).
+fn write_json_escaped(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    let mut start = 0;
+
+    // All escaped characters are ASCII, so byte indices are always character boundaries here.
+    for (i, byte) in value.bytes().enumerate() {
+        if byte == b'"' || byte == b'\\' || byte < 0x20 {
+            f.write_str(&value[start..i])?;
+
+            match byte {
+                b'"' => f.write_str("\\\"")?,
+                b'\\' => f.write_str("\\\\")?,
+                b'\n' => f.write_str("\\n")?,
+                b'\r' => f.write_str("\\r")?,
+                b'\t' => f.write_str("\\t")?,
+                other => write!(f, "\\u{other:04x}")?,
+            }
+
+            start = i + 1;
+        }
+    }
+
+    f.write_str(&value[start..])
+}
+
+/// Check that the line contains the given literal at the index, returning the index after it.
+fn expect_literal(
+    line: &str,
+    index: usize,
+    literal: &str,
+    reason: &'static str,
+) -> Result<usize, Error> {
+    if line
+        .get(index..)
+        .is_some_and(|rest| rest.starts_with(literal))
+    {
+        Ok(index + literal.len())
+    } else {
+        Err(Error::InvalidLine {
+            offset: index.min(line.len()),
+            reason,
+        })
+    }
+}
+
+/// Read the contents of a JSON string literal starting at the index (just after the opening quote).
+///
+/// Returns the value and the index of the closing quote. We only allocate if the value contains escaped characters,
+/// which is rare.
+fn read_json_string<'a>(
+    line: &'a str,
+    index: usize,
+    unterminated_reason: &'static str,
+    invalid_escape_reason: &'static str,
+) -> Result<(Cow<'a, str>, usize), Error> {
+    let bytes = line.as_bytes();
+    let mut end = index;
+    let mut escaped = false;
+
+    loop {
+        match bytes.get(end) {
+            Some(b'"') => break,
+            Some(b'\\') => {
+                escaped = true;
+                end += 2;
+            }
+            Some(_) => end += 1,
+            None => {
+                return Err(Error::InvalidLine {
+                    offset: index,
+                    reason: unterminated_reason,
+                });
+            }
+        }
+    }
+
+    // The quotes are ASCII, so these indices are always at character boundaries.
+    let value = if escaped {
+        line.get(index - 1..end + 1)
+            .and_then(|literal| serde_json::from_str::<String>(literal).ok())
+            .map(Cow::Owned)
+    } else {
+        line.get(index..end).map(Cow::Borrowed)
+    };
+
+    value.map(|value| (value, end)).ok_or(Error::InvalidLine {
+        offset: index,
+        reason: invalid_escape_reason,
+    })
+}
+
 const DIGEST_LEN: usize = 32;
-const TIMESTAMP_LEN: usize = 14;
 
 const DIGEST_KEY: &str = "digest";
 const DIGEST_KEY_LEN: usize = DIGEST_KEY.len();
@@ -106,28 +255,7 @@ const CONTENT_KEY_LEN: usize = CONTENT_KEY.len();
 
 impl<'a> SnapshotLine<'a> {
     pub fn new(digest: Sha1Digest, content: &'a str) -> Self {
-        let bytes = content.as_bytes();
-
-        let closing_whitespace = if bytes[bytes.len() - 3..] == DEFAULT_CLOSING_WHITESPACE
-            && bytes[bytes.len() - 4] != b'\r'
-            && bytes[bytes.len() - 4] != b'\n'
-        {
-            None
-        } else {
-            Some(
-                content
-                    .chars()
-                    .rev()
-                    .take_while(|ch| *ch == '\r' || *ch == '\n')
-                    .collect::<Vec<_>>(),
-            )
-        };
-
-        let content = &content[0..content.len()
-            - closing_whitespace
-                .as_ref()
-                .map(|closing_whitespace| closing_whitespace.len())
-                .unwrap_or(DEFAULT_CLOSING_WHITESPACE.len())];
+        let (content, closing_whitespace) = split_closing_whitespace(content);
 
         Self {
             digest,
@@ -139,10 +267,38 @@ impl<'a> SnapshotLine<'a> {
         }
     }
 
+    /// Move any trailing whitespace in the content into the closing whitespace and then validate the digest.
+    ///
+    /// This is intended for lines where the closing whitespace has been lost from the metadata but is still present
+    /// in the content, and it has no effect on the line if the content has no trailing whitespace.
+    pub fn repair_closing_whitespace(&mut self, hasher: &mut sha1::Sha1) -> Result<(), Sha1Digest> {
+        if self.content.ends_with(['\r', '\n']) {
+            let (content_len, closing_whitespace) = {
+                let (content, closing_whitespace) = split_closing_whitespace(&self.content);
+
+                (content.len(), closing_whitespace)
+            };
+
+            match &mut self.content {
+                Cow::Borrowed(content) => {
+                    let borrowed: &'a str = *content;
+                    *content = &borrowed[..content_len];
+                }
+                Cow::Owned(content) => {
+                    content.truncate(content_len);
+                }
+            }
+
+            self.closing_whitespace = closing_whitespace;
+        }
+
+        self.validate(hasher)
+    }
+
     pub fn into_owned(self) -> SnapshotLine<'static> {
         SnapshotLine {
             digest: self.digest,
-            expected_digest: self.expected_digest,
+            expected_digest: self.expected_digest.map(Digest::into_owned),
             closing_whitespace: self.closing_whitespace,
             timestamp: self.timestamp,
             url: self.url.map(|url| url.into_owned().into()),
@@ -150,196 +306,660 @@ impl<'a> SnapshotLine<'a> {
         }
     }
 
+    /// The stored URL if present, or otherwise a canonical URL computed from the content.
+    ///
+    /// The content format is detected automatically. Data-format snapshots may not include the author, and content in
+    /// an unrecognized format can't be used, so in these cases no URL can be computed.
+    pub fn canonical_url(&self) -> Result<Option<Cow<'_, str>>, Error> {
+        match &self.url {
+            Some(url) => Ok(Some(url.as_ref().into())),
+            None => match tweet::TweetSnapshotKind::from_content(&self.content) {
+                Some(kind) => Ok(crate::canonical::canonical_url(
+                    &kind.parse(&self.content)?,
+                    false,
+                )
+                .map(Cow::from)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// The tweet ID and author ID, extracted from the content without deserializing the full snapshot.
+    ///
+    /// Returns `None` if the content is not in a recognized format or does not include an author ID.
+    pub fn tweet_ids(&self) -> Result<Option<(u64, u64)>, Error> {
+        let ids = match tweet::TweetSnapshotKind::from_content(&self.content) {
+            Some(tweet::TweetSnapshotKind::Data) => {
+                let content = serde_json::from_str::<ids::DataContent>(&self.content)?;
+
+                content
+                    .data
+                    .author_id
+                    .map(|author_id| (content.data.id, author_id))
+            }
+            Some(tweet::TweetSnapshotKind::Flat) => {
+                let content = serde_json::from_str::<ids::FlatContent>(&self.content)?;
+
+                Some((content.id_str, content.user.id_str))
+            }
+            None => None,
+        };
+
+        ids.map(|(id, author_id)| Ok((ids::parse(&id)?, ids::parse(&author_id)?)))
+            .transpose()
+    }
+
+    /// The tweet ID, extracted from the content without deserializing the full snapshot.
+    ///
+    /// Returns `None` if the content is not in a recognized format. Unlike `tweet_ids`, this does not require an
+    /// author ID.
+    pub fn tweet_id(&self) -> Result<Option<u64>, Error> {
+        let id = match tweet::TweetSnapshotKind::from_content(&self.content) {
+            Some(tweet::TweetSnapshotKind::Data) => Some(
+                serde_json::from_str::<ids::DataContent>(&self.content)?
+                    .data
+                    .id,
+            ),
+            Some(tweet::TweetSnapshotKind::Flat) => {
+                Some(serde_json::from_str::<ids::FlatTweetId>(&self.content)?.id_str)
+            }
+            None => None,
+        };
+
+        id.map(|id| ids::parse(&id)).transpose()
+    }
+
+    /// The status ID in the stored URL, if there is one.
+    pub fn url_tweet_id(&self) -> Option<u64> {
+        const STATUS_SEGMENT: &str = "/status/";
+
+        self.url.as_ref().and_then(|url| {
+            let start = url.find(STATUS_SEGMENT)? + STATUS_SEGMENT.len();
+            let len = url[start..]
+                .find(|ch: char| !ch.is_ascii_digit())
+                .unwrap_or(url.len() - start);
+
+            url[start..start + len].parse().ok()
+        })
+    }
+
+    /// The URL status ID and content tweet ID, if both are available and they differ.
+    pub fn url_tweet_id_mismatch(&self) -> Result<Option<(u64, u64)>, Error> {
+        match self.url_tweet_id() {
+            Some(url_tweet_id) => Ok(self
+                .tweet_id()?
+                .map(|tweet_id| (url_tweet_id, tweet_id))
+                .filter(|(url_tweet_id, tweet_id)| url_tweet_id != tweet_id)),
+            None => Ok(None),
+        }
+    }
+
     pub fn validate(&self, hasher: &mut sha1::Sha1) -> Result<(), Sha1Digest> {
-        hasher.update(self.content.as_bytes());
+        let digest = self.digest_under(DigestPolicy::Raw, hasher);
 
+        if digest.ct_eq(&self.digest) {
+            Ok(())
+        } else {
+            Err(digest)
+        }
+    }
+
+    /// The closing whitespace as it was captured (i.e. the default if none is specified).
+    pub fn closing_whitespace_bytes(&self) -> Vec<u8> {
         match self.closing_whitespace.as_ref() {
             Some(closing_whitespace) => {
                 // We simply ignore any unexpected whitespace characters here.
-                let bytes = closing_whitespace
+                closing_whitespace
                     .iter()
                     .filter_map(|whitespace| match whitespace {
                         '\n' => Some(b'\n'),
                         '\r' => Some(b'\r'),
                         _ => None,
                     })
-                    .collect::<Vec<_>>();
+                    .collect()
+            }
+            None => DEFAULT_CLOSING_WHITESPACE.to_vec(),
+        }
+    }
 
-                hasher.update(&bytes);
+    /// Compute the digest of the content with closing whitespace handled according to the given policy.
+    ///
+    /// Only `DigestPolicy::Raw` is guaranteed to produce the line's digest for a valid line.
+    pub fn digest_under(&self, policy: DigestPolicy, hasher: &mut sha1::Sha1) -> Sha1Digest {
+        match policy {
+            DigestPolicy::Raw => {
+                hasher.update(self.content.as_bytes());
+                hasher.update(self.closing_whitespace_bytes());
             }
-            None => {
+            DigestPolicy::NormalizeTrailingWhitespace => {
+                hasher.update(self.content.trim_end().as_bytes());
                 hasher.update(DEFAULT_CLOSING_WHITESPACE);
             }
+            DigestPolicy::StripAllTrailingWhitespace => {
+                hasher.update(self.content.trim_end().as_bytes());
+            }
         }
 
-        let digest = Sha1Digest(hasher.finalize_reset().into());
+        Sha1Digest(hasher.finalize_reset().into())
+    }
 
-        if digest == self.digest {
-            Ok(())
-        } else {
-            Err(digest)
+    /// Serialize to the line format, failing if the content is not well-formed JSON.
+    ///
+    /// The `Display` implementation writes the content as-is, since archived content may be malformed, so this should be
+    /// used when the output must be valid JSON.
+    pub fn to_checked_string(&self) -> Result<String, serde_json::Error> {
+        self.validate_content_json()?;
+
+        Ok(self.to_string())
+    }
+
+    /// Check that the content is well-formed JSON.
+    ///
+    /// This does not attempt to deserialize the content into any particular model, and it does not allocate.
+    pub fn validate_content_json(&self) -> Result<(), serde_json::Error> {
+        serde_json::from_str::<serde::de::IgnoredAny>(&self.content).map(|_| ())
+    }
+
+    pub fn explain(&self, hasher: &mut sha1::Sha1) -> SnapshotLineExplanation {
+        SnapshotLineExplanation {
+            stored_digest: self.digest,
+            computed_digest: self.digest_under(DigestPolicy::Raw, hasher),
+            closing_whitespace: self.closing_whitespace_bytes(),
+            content_len: self.content.len(),
+            content_json_error: self
+                .validate_content_json()
+                .err()
+                .map(|error| error.to_string()),
         }
     }
 
     pub fn parse(line: &'a str) -> Result<Self, Error> {
+        let invalid = |offset, reason| Error::InvalidLine { offset, reason };
+        let has_key =
+            |index: usize, key: &str| line.get(index..).is_some_and(|rest| rest.starts_with(key));
+
         let mut index = DIGEST_KEY_LEN + 5;
 
-        let digest = line[index..index + DIGEST_LEN]
-            .parse::<Sha1Digest>()
-            .map_err(|_| Error::InvalidLine)?;
+        let digest = line
+            .get(index..index + DIGEST_LEN)
+            .and_then(|digest_str| digest_str.parse::<Sha1Digest>().ok())
+            .ok_or(invalid(index, "invalid digest"))?;
+
+        index = expect_literal(line, index + DIGEST_LEN, "\",\"", "invalid digest")?;
+
+        let expected_digest = if has_key(index, EXPECTED_DIGEST_KEY) {
+            index = expect_literal(
+                line,
+                index + EXPECTED_DIGEST_KEY_LEN,
+                "\":\"",
+                "invalid expected digest",
+            )?;
+
+            let (expected_digest_str, end) = read_json_string(
+                line,
+                index,
+                "unterminated expected digest",
+                "invalid expected digest",
+            )?;
+
+            index = expect_literal(line, end, "\",\"", "invalid expected digest")?;
+
+            // Expected digests come from CDX results and are not necessarily valid SHA-1 digests, so we can't
+            // assume a fixed length here, and we preserve values that can't be decoded.
+            Some(match expected_digest_str {
+                Cow::Borrowed(value) => {
+                    Digest::parse_str(value).unwrap_or_else(|_| Digest::Invalid(value.into()))
+                }
+                Cow::Owned(value) => Digest::parse_str(&value)
+                    .map(Digest::into_owned)
+                    .unwrap_or_else(|_| Digest::Invalid(value.into())),
+            })
+        } else {
+            None
+        };
 
-        index += DIGEST_LEN + 3;
+        let closing_whitespace = if has_key(index, CLOSING_WHITESPACE_KEY) {
+            let mut closing_whitespace = vec![];
 
-        if line.len() >= index + 2 {
-            let expected_digest = if line[index..].starts_with(EXPECTED_DIGEST_KEY) {
-                index += EXPECTED_DIGEST_KEY_LEN + 3;
+            index = expect_literal(
+                line,
+                index + CLOSING_WHITESPACE_KEY_LEN,
+                "\":\"",
+                "invalid closing whitespace",
+            )?;
 
-                let expected_digest = line[index..index + DIGEST_LEN]
-                    .parse::<Sha1Digest>()
-                    .map_err(|_| Error::InvalidLine)?;
+            let bytes = line.as_bytes();
 
-                index += DIGEST_LEN + 3;
+            while bytes.get(index) != Some(&b'"') {
+                match (bytes.get(index), bytes.get(index + 1)) {
+                    (Some(b'\\'), Some(b'n')) => closing_whitespace.push('\n'),
+                    (Some(b'\\'), Some(b'r')) => closing_whitespace.push('\r'),
+                    _ => return Err(invalid(index, "invalid closing whitespace")),
+                }
 
-                Some(expected_digest)
-            } else {
-                None
-            };
+                index += 2;
+            }
 
-            let closing_whitespace = if line[index..].starts_with(CLOSING_WHITESPACE_KEY) {
-                let mut closing_whitespace = vec![];
+            index = expect_literal(line, index, "\",\"", "invalid closing whitespace")?;
 
-                index += CLOSING_WHITESPACE_KEY_LEN + 3;
+            Some(closing_whitespace)
+        } else {
+            None
+        };
 
-                let mut next = &line[index..index + 1];
-                let mut failed = false;
-                let mut i = 0;
+        let timestamp = if has_key(index, TIMESTAMP_KEY) {
+            index = expect_literal(
+                line,
+                index + TIMESTAMP_KEY_LEN,
+                "\":\"",
+                "invalid timestamp",
+            )?;
 
-                while next != "\"" {
-                    if i % 2 == 0 && next != "\\" {
-                        failed = true;
-                    }
+            // Timestamps have 14 digits, or 17 if they include milliseconds.
+            let len = line.as_bytes()[index..]
+                .iter()
+                .take_while(|byte| byte.is_ascii_digit())
+                .count();
 
-                    if i % 2 == 1 {
-                        match next {
-                            "n" => closing_whitespace.push('\n'),
-                            "r" => closing_whitespace.push('\r'),
-                            _ => {
-                                failed = true;
-                            }
-                        }
-                    }
+            let timestamp = line[index..index + len]
+                .parse::<Timestamp>()
+                .map_err(|_| invalid(index, "invalid timestamp"))?;
 
-                    i += 1;
-                    next = &line[index + i..index + i + 1];
-                }
+            index = expect_literal(line, index + len, "\",\"", "invalid timestamp")?;
 
-                if failed {
-                    Err(Error::InvalidLine)
-                } else {
-                    index += i + 3;
+            Some(timestamp)
+        } else {
+            None
+        };
 
-                    Ok(Some(closing_whitespace))
-                }
-            } else {
-                Ok(None)
-            }?;
+        let url = if has_key(index, URL_KEY) {
+            index = expect_literal(line, index + URL_KEY_LEN, "\":\"", "invalid URL")?;
 
-            let timestamp = if line[index..].starts_with(TIMESTAMP_KEY) {
-                index += TIMESTAMP_KEY_LEN + 3;
+            let (url, end) =
+                read_json_string(line, index, "unterminated URL", "invalid URL escape")?;
 
-                let timestamp = line[index..index + TIMESTAMP_LEN]
-                    .parse::<Timestamp>()
-                    .map_err(|_| Error::InvalidLine)?;
+            index = expect_literal(line, end, "\",\"", "invalid URL")?;
 
-                index += TIMESTAMP_LEN + 3;
+            Some(url)
+        } else {
+            None
+        };
 
-                Some(timestamp)
-            } else {
-                None
-            };
+        if !has_key(index, CONTENT_KEY) || !line.ends_with('}') {
+            return Err(invalid(index.min(line.len()), "missing content"));
+        }
 
-            let url = if line[index..].starts_with(URL_KEY) {
-                index += URL_KEY_LEN + 3;
+        index = expect_literal(line, index + CONTENT_KEY_LEN, "\":", "missing content")?;
 
-                let mut failed = false;
-                let mut i = 0;
+        let content = line
+            .get(index..line.len() - 1)
+            .ok_or(invalid(index.min(line.len()), "missing content"))?;
 
-                while &line[index + i..index + i + 1] != "\"" {
-                    i += 1;
+        Ok(Self {
+            digest,
+            expected_digest,
+            closing_whitespace,
+            timestamp,
+            url,
+            content: content.into(),
+        })
+    }
 
-                    if index + i >= line.len() {
-                        failed = true;
-                    }
-                }
+    /// Parse a line by deserializing it as a JSON object.
+    ///
+    /// This is slower than `parse`, but it accepts keys in any order and arbitrary whitespace between tokens. The
+    /// content is preserved exactly as it appears in the line.
+    pub fn parse_lenient(line: &'a str) -> Result<Self, Error> {
+        let lenient = serde_json::from_str::<LenientSnapshotLine<'a>>(line)?;
+
+        Ok(Self {
+            digest: lenient.digest,
+            // As in `parse`, we preserve expected digests that can't be decoded.
+            expected_digest: lenient.expected_digest.map(
+                |expected_digest| match Digest::parse_str(&expected_digest) {
+                    Ok(Digest::Valid(digest)) => Digest::Valid(digest),
+                    _ => Digest::Invalid(expected_digest),
+                },
+            ),
+            closing_whitespace: lenient.closing_whitespace,
+            timestamp: lenient.timestamp,
+            url: lenient.url,
+            content: lenient.content.get().into(),
+        })
+    }
 
-                if failed {
-                    Err(Error::InvalidLine)
-                } else {
-                    let url = line[index..index + i].into();
-                    index += i + 3;
+    /// Check that each line parses, has a valid digest, and is in order.
+    ///
+    /// The content is not checked (see `validate_lines_with_content`).
+    pub fn validate_lines<R: std::io::Read>(
+        lines: std::io::Lines<std::io::BufReader<R>>,
+    ) -> Result<SnapshotLineValidation, std::io::Error> {
+        Self::validate_lines_checking(lines, false)
+    }
 
-                    Ok(Some(url))
-                }
-            } else {
-                Ok(None)
-            }?;
+    /// Validate lines as in `validate_lines`, but also check that the content of each line is well-formed JSON.
+    ///
+    /// This requires an extra parsing pass over the content of each line.
+    pub fn validate_lines_with_content<R: std::io::Read>(
+        lines: std::io::Lines<std::io::BufReader<R>>,
+    ) -> Result<SnapshotLineValidation, std::io::Error> {
+        Self::validate_lines_checking(lines, true)
+    }
 
-            index += CONTENT_KEY_LEN + 2;
+    fn validate_lines_checking<R: std::io::Read>(
+        lines: std::io::Lines<std::io::BufReader<R>>,
+        check_content: bool,
+    ) -> Result<SnapshotLineValidation, std::io::Error> {
+        let mut validation = SnapshotLineValidation::default();
+        let mut hasher = Sha1::default();
+        let mut last_digest = Sha1Digest::MIN;
 
-            Ok(Self {
-                digest,
-                expected_digest,
-                closing_whitespace,
-                timestamp,
-                url,
-                content: line[index..line.len() - 1].into(),
-            })
-        } else {
-            Err(Error::InvalidLine)
+        let mut header = None;
+
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+
+            if i == 0
+                && let Some(parsed_header) = SnapshotFileHeader::parse_line(&line)
+            {
+                validation.record_header(parsed_header, &mut header);
+
+                continue;
+            }
+
+            let outcome =
+                LineOutcome::check(&line, i + 1, header.as_ref(), check_content, &mut hasher);
+
+            validation.record(outcome, &mut last_digest);
         }
+
+        Ok(validation)
     }
 
-    pub fn validate_lines<R: std::io::Read>(
+    /// Validate lines as in `validate_lines`, but parsing and hashing chunks of lines on multiple threads.
+    ///
+    /// The results are identical to those of `validate_lines`.
+    pub fn validate_lines_parallel<R: std::io::Read>(
+        lines: std::io::Lines<std::io::BufReader<R>>,
+    ) -> Result<SnapshotLineValidation, std::io::Error> {
+        Self::validate_lines_parallel_checking(lines, false)
+    }
+
+    /// Validate lines as in `validate_lines_with_content`, but on multiple threads.
+    pub fn validate_lines_parallel_with_content<R: std::io::Read>(
         lines: std::io::Lines<std::io::BufReader<R>>,
     ) -> Result<SnapshotLineValidation, std::io::Error> {
+        Self::validate_lines_parallel_checking(lines, true)
+    }
+
+    fn validate_lines_parallel_checking<R: std::io::Read>(
+        lines: std::io::Lines<std::io::BufReader<R>>,
+        check_content: bool,
+    ) -> Result<SnapshotLineValidation, std::io::Error> {
+        use rayon::prelude::*;
+
+        const CHUNK_SIZE: usize = 16 * 1024;
+
         let mut validation = SnapshotLineValidation::default();
-        let mut hasher = Sha1::default();
         let mut last_digest = Sha1Digest::MIN;
 
-        for (i, line) in lines.enumerate() {
+        let mut header = None;
+        let mut lines = lines.enumerate();
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+
+        if let Some((i, line)) = lines.next() {
             let line = line?;
-            match SnapshotLine::parse(&line) {
-                Ok(snapshot_line) => match snapshot_line.validate(&mut hasher) {
-                    Ok(()) => {
-                        if snapshot_line.digest > last_digest {
-                            validation.valid_count += 1;
-                            last_digest = snapshot_line.digest;
-                        } else {
-                            validation.out_of_order.push(snapshot_line.digest);
+
+            match SnapshotFileHeader::parse_line(&line) {
+                Some(parsed_header) => validation.record_header(parsed_header, &mut header),
+                None => chunk.push((i, line)),
+            }
+        }
+
+        loop {
+            for (i, line) in lines.by_ref().take(CHUNK_SIZE - chunk.len()) {
+                chunk.push((i, line?));
+            }
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            let outcomes = chunk
+                .par_iter()
+                .map_init(Sha1::default, |hasher, (i, line)| {
+                    LineOutcome::check(line, i + 1, header.as_ref(), check_content, hasher)
+                })
+                .collect::<Vec<_>>();
+
+            // Ordering depends on the previous line, so we check it serially.
+            for outcome in outcomes {
+                validation.record(outcome, &mut last_digest);
+            }
+
+            chunk.clear();
+        }
+
+        Ok(validation)
+    }
+
+    /// Keep only the latest capture of each tweet.
+    ///
+    /// Undated captures are considered earlier than dated ones, and the last capture seen wins ties. Lines whose
+    /// tweet IDs can't be determined are all retained. The result is sorted by digest.
+    pub fn dedup_by_tweet_id<I: IntoIterator<Item = Result<SnapshotLine<'static>, Error>>>(
+        lines: I,
+    ) -> Result<Vec<SnapshotLine<'static>>, Error> {
+        let mut by_tweet_id = std::collections::HashMap::<u64, SnapshotLine<'static>>::new();
+        let mut result = vec![];
+
+        for snapshot_line in lines {
+            let snapshot_line = snapshot_line?;
+
+            match snapshot_line.tweet_id()? {
+                Some(tweet_id) => match by_tweet_id.entry(tweet_id) {
+                    std::collections::hash_map::Entry::Occupied(mut entry) => {
+                        if snapshot_line.timestamp >= entry.get().timestamp {
+                            entry.insert(snapshot_line);
                         }
                     }
-                    Err(actual_digest) => {
-                        validation
-                            .unexpected_digests
-                            .push((snapshot_line.digest, actual_digest));
+                    std::collections::hash_map::Entry::Vacant(entry) => {
+                        entry.insert(snapshot_line);
                     }
                 },
-                Err(_) => {
-                    validation.invalid_lines.push(i + 1);
+                None => {
+                    result.push(snapshot_line);
                 }
             }
         }
 
-        Ok(validation)
+        result.extend(by_tweet_id.into_values());
+        result.sort_by_key(|snapshot_line| snapshot_line.digest);
+
+        Ok(result)
+    }
+}
+
+/// The fields of a snapshot line, for use in lenient parsing.
+#[derive(serde::Deserialize)]
+struct LenientSnapshotLine<'a> {
+    digest: Sha1Digest,
+    #[serde(borrow)]
+    expected_digest: Option<Cow<'a, str>>,
+    #[serde(with = "closing_whitespace", default)]
+    closing_whitespace: Option<Vec<char>>,
+    timestamp: Option<Timestamp>,
+    #[serde(borrow)]
+    url: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    content: &'a serde_json::value::RawValue,
+}
+
+/// Diagnostic information about a single snapshot line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SnapshotLineExplanation {
+    pub stored_digest: Sha1Digest,
+    pub computed_digest: Sha1Digest,
+    pub closing_whitespace: Vec<u8>,
+    /// The length of the content in bytes (not including closing whitespace).
+    pub content_len: usize,
+    pub content_json_error: Option<String>,
+}
+
+impl SnapshotLineExplanation {
+    pub fn is_valid(&self) -> bool {
+        self.stored_digest == self.computed_digest && self.content_json_error.is_none()
+    }
+
+    /// Find the line with the given digest and explain it.
+    ///
+    /// Returns the one-based line number and the explanation, or `None` if no line has the digest.
+    pub fn find<R: std::io::Read>(
+        lines: std::io::Lines<std::io::BufReader<R>>,
+        digest: Sha1Digest,
+    ) -> Result<Option<(usize, Self)>, Error> {
+        let mut hasher = Sha1::default();
+        let digest_str = digest.to_string();
+        let digest_index = DIGEST_KEY_LEN + 5;
+
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+
+            // Avoid parsing lines that can't match.
+            if line.get(digest_index..digest_index + DIGEST_LEN) == Some(digest_str.as_str()) {
+                let snapshot_line = SnapshotLine::parse(&line)?;
+
+                return Ok(Some((i + 1, snapshot_line.explain(&mut hasher))));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+const SNAPSHOT_FILE_HEADER_PREFIX: &str = "{\"__archivindex_version\":";
+
+/// The current version of the snapshot line file format.
+pub const SNAPSHOT_FILE_VERSION: u32 = 1;
+
+/// An optional first line in a snapshot line file.
+///
+/// Files without a header use version 1 and the standard default closing whitespace (`\r\r\n`).
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SnapshotFileHeader {
+    #[serde(rename = "__archivindex_version")]
+    pub version: u32,
+    /// The closing whitespace for lines without a `closing_whitespace` field.
+    pub default_closing_whitespace: String,
+}
+
+impl Default for SnapshotFileHeader {
+    fn default() -> Self {
+        Self {
+            version: SNAPSHOT_FILE_VERSION,
+            default_closing_whitespace: "\r\r\n".to_string(),
+        }
     }
 }
 
+impl SnapshotFileHeader {
+    pub fn new(default_closing_whitespace: &str) -> Result<Self, Error> {
+        if default_closing_whitespace
+            .chars()
+            .all(|ch| ch == '\r' || ch == '\n')
+        {
+            Ok(Self {
+                version: SNAPSHOT_FILE_VERSION,
+                default_closing_whitespace: default_closing_whitespace.to_string(),
+            })
+        } else {
+            Err(Error::InvalidClosingWhitespace(
+                default_closing_whitespace.to_string(),
+            ))
+        }
+    }
+
+    /// Parse a line if it is a header.
+    pub fn parse_line(line: &str) -> Option<Result<Self, Error>> {
+        line.starts_with(SNAPSHOT_FILE_HEADER_PREFIX).then(|| {
+            let header = serde_json::from_str::<Self>(line)?;
+
+            // Validate the declared closing whitespace.
+            Self::new(&header.default_closing_whitespace).map(|_| header)
+        })
+    }
+
+    fn is_standard(&self) -> bool {
+        self.default_closing_whitespace.as_bytes() == DEFAULT_CLOSING_WHITESPACE
+    }
+
+    /// Make the closing whitespace of a line from this file explicit if it uses the file's default.
+    pub fn resolve(&self, snapshot_line: &mut SnapshotLine) {
+        if snapshot_line.closing_whitespace.is_none() && !self.is_standard() {
+            snapshot_line.closing_whitespace =
+                Some(self.default_closing_whitespace.chars().collect());
+        }
+    }
+
+    /// Convert a line's closing whitespace to the representation used in this file.
+    pub fn encode<'b, 'c>(&self, snapshot_line: &'b SnapshotLine<'c>) -> Cow<'b, SnapshotLine<'c>> {
+        if self.is_standard() {
+            Cow::Borrowed(snapshot_line)
+        } else if snapshot_line.closing_whitespace_bytes()
+            == self.default_closing_whitespace.as_bytes()
+        {
+            let mut encoded = snapshot_line.clone();
+            encoded.closing_whitespace = None;
+
+            Cow::Owned(encoded)
+        } else if snapshot_line.closing_whitespace.is_none() {
+            let mut encoded = snapshot_line.clone();
+            encoded.closing_whitespace = Some(
+                DEFAULT_CLOSING_WHITESPACE
+                    .iter()
+                    .map(|byte| char::from(*byte))
+                    .collect(),
+            );
+
+            Cow::Owned(encoded)
+        } else {
+            Cow::Borrowed(snapshot_line)
+        }
+    }
+}
+
+/// Separate trailing whitespace from content.
+///
+/// The closing whitespace is `None` if it is the default.
+fn split_closing_whitespace(content: &str) -> (&str, Option<Vec<char>>) {
+    let trimmed = content.trim_end_matches(['\r', '\n']);
+    let closing_whitespace = &content[trimmed.len()..];
+
+    if closing_whitespace.as_bytes() == DEFAULT_CLOSING_WHITESPACE {
+        (trimmed, None)
+    } else {
+        (trimmed, Some(closing_whitespace.chars().collect()))
+    }
+}
+
+/// A line that could not be parsed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InvalidLine {
+    /// The one-based line number.
+    pub line: usize,
+    /// The byte offset within the line at which parsing failed.
+    pub offset: usize,
+    pub reason: &'static str,
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct SnapshotLineValidation {
     pub valid_count: usize,
-    pub invalid_lines: Vec<usize>,
+    pub invalid_lines: Vec<InvalidLine>,
     pub unexpected_digests: Vec<(Sha1Digest, Sha1Digest)>,
     pub out_of_order: Vec<Sha1Digest>,
+    /// Lines with valid digests whose content is not well-formed JSON (only checked when requested).
+    pub invalid_content: Vec<Sha1Digest>,
 }
 
 impl SnapshotLineValidation {
@@ -347,6 +967,143 @@ impl SnapshotLineValidation {
         self.invalid_lines.is_empty()
             && self.unexpected_digests.is_empty()
             && self.out_of_order.is_empty()
+            && self.invalid_content.is_empty()
+    }
+
+    fn record_header(
+        &mut self,
+        parsed_header: Result<SnapshotFileHeader, Error>,
+        header: &mut Option<SnapshotFileHeader>,
+    ) {
+        match parsed_header {
+            Ok(parsed_header) => {
+                *header = Some(parsed_header);
+            }
+            Err(_) => {
+                self.invalid_lines.push(InvalidLine {
+                    line: 1,
+                    offset: 0,
+                    reason: "invalid header",
+                });
+            }
+        }
+    }
+
+    fn record(&mut self, outcome: LineOutcome, last_digest: &mut Sha1Digest) {
+        match outcome {
+            LineOutcome::Valid(digest) => {
+                if digest > *last_digest {
+                    self.valid_count += 1;
+                    *last_digest = digest;
+                } else {
+                    self.out_of_order.push(digest);
+                }
+            }
+            LineOutcome::InvalidLine(invalid_line) => {
+                self.invalid_lines.push(invalid_line);
+            }
+            LineOutcome::UnexpectedDigest(digest, actual_digest) => {
+                self.unexpected_digests.push((digest, actual_digest));
+            }
+            LineOutcome::InvalidContent(digest) => {
+                self.invalid_content.push(digest);
+            }
+        }
+    }
+}
+
+/// The result of validating a single line, before checking its order.
+enum LineOutcome {
+    Valid(Sha1Digest),
+    InvalidLine(InvalidLine),
+    UnexpectedDigest(Sha1Digest, Sha1Digest),
+    InvalidContent(Sha1Digest),
+}
+
+impl LineOutcome {
+    fn check(
+        line: &str,
+        line_number: usize,
+        header: Option<&SnapshotFileHeader>,
+        check_content: bool,
+        hasher: &mut Sha1,
+    ) -> Self {
+        match SnapshotLine::parse(line) {
+            Ok(mut snapshot_line) => {
+                if let Some(header) = header {
+                    header.resolve(&mut snapshot_line);
+                }
+
+                match snapshot_line.validate(hasher) {
+                    Ok(()) => {
+                        if check_content && snapshot_line.validate_content_json().is_err() {
+                            Self::InvalidContent(snapshot_line.digest)
+                        } else {
+                            Self::Valid(snapshot_line.digest)
+                        }
+                    }
+                    Err(actual_digest) => {
+                        Self::UnexpectedDigest(snapshot_line.digest, actual_digest)
+                    }
+                }
+            }
+            Err(error) => {
+                let (offset, reason) = match error {
+                    Error::InvalidLine { offset, reason } => (offset, reason),
+                    _ => (0, "invalid line"),
+                };
+
+                Self::InvalidLine(InvalidLine {
+                    line: line_number,
+                    offset,
+                    reason,
+                })
+            }
+        }
+    }
+}
+
+/// Minimal representations of the content formats for extracting IDs.
+mod ids {
+    use std::borrow::Cow;
+
+    #[derive(serde::Deserialize)]
+    pub struct DataContent<'a> {
+        #[serde(borrow)]
+        pub data: DataTweet<'a>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct DataTweet<'a> {
+        #[serde(borrow)]
+        pub id: Cow<'a, str>,
+        #[serde(borrow)]
+        pub author_id: Option<Cow<'a, str>>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct FlatContent<'a> {
+        #[serde(borrow)]
+        pub id_str: Cow<'a, str>,
+        #[serde(borrow)]
+        pub user: FlatUser<'a>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct FlatTweetId<'a> {
+        #[serde(borrow)]
+        pub id_str: Cow<'a, str>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct FlatUser<'a> {
+        #[serde(borrow)]
+        pub id_str: Cow<'a, str>,
+    }
+
+    pub fn parse(id: &str) -> Result<u64, super::Error> {
+        id.parse()
+            .map_err(|_| super::Error::InvalidId(id.to_string()))
     }
 }
 
@@ -412,6 +1169,15 @@ mod tests {
     use std::io::BufRead;
 
     use super::*;
+    use archivindex_wbm::testing::{sha1_digest, snapshot_content};
+    use birdsite::model::wxj::data;
+
+    /// The snapshot line for the shared test content for the given tweet ID.
+    fn test_line(id: u64) -> (Sha1Digest, SnapshotLine<'static>) {
+        let (digest, content) = snapshot_content(id);
+
+        (digest, SnapshotLine::new(digest, &content).into_owned())
+    }
 
     #[test]
     fn parse_inferred_url() -> Result<(), Box<dyn std::error::Error>> {
@@ -426,6 +1192,41 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_invalid_expected_digest() -> Result<(), Box<dyn std::error::Error>> {
+        // Too short, the right length but not Base32, and not safe to write without escaping.
+        for invalid_expected_digest in [
+            "HYT52YPEOCHJD5FZINSDYXGQZI22WJ4",
+            "HYT52YPEOCHJD5FZINSDYXGQZI22WJ41",
+            "HYT52\\YPEOCHJD5\"\u{1}",
+        ] {
+            let line = include_str!("../../../examples/wxj/inferred-url-01.json")
+                .trim()
+                .replacen(
+                    ",\"timestamp\"",
+                    &format!(
+                        ",\"expected_digest\":{},\"timestamp\"",
+                        serde_json::to_string(invalid_expected_digest)?
+                    ),
+                    1,
+                );
+
+            let parsed = SnapshotLine::parse(&line)?;
+            let expected = Some(Digest::Invalid(invalid_expected_digest.into()));
+
+            assert_eq!(parsed.expected_digest, expected);
+            assert_eq!(SnapshotLine::parse_lenient(&line)?, parsed);
+            assert_eq!(
+                serde_json::from_str::<Snapshot<serde_json::Value>>(&line)?.expected_digest,
+                expected
+            );
+            assert_eq!(line, parsed.to_string());
+            assert_eq!(parsed.validate(&mut Default::default()), Ok(()));
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn parse_examples() -> Result<(), Box<dyn std::error::Error>> {
         let lines = include_str!("../../../examples/wxj/lines-01.ndjson").split("\n");
@@ -441,6 +1242,315 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_lenient() -> Result<(), Box<dyn std::error::Error>> {
+        let line = include_str!("../../../examples/wxj/inferred-url-01.json").trim();
+        let expected = SnapshotLine::parse(line)?;
+
+        assert_eq!(SnapshotLine::parse_lenient(line)?, expected);
+
+        let fields = serde_json::from_str::<
+            std::collections::HashMap<&str, &serde_json::value::RawValue>,
+        >(line)?;
+        let content = fields["content"];
+
+        let reordered = format!(
+            "{{\"url\":{},\"content\":{},\"timestamp\":{},\"digest\":{}}}",
+            fields["url"], content, fields["timestamp"], fields["digest"]
+        );
+
+        assert!(SnapshotLine::parse(&reordered).is_err());
+        assert_eq!(SnapshotLine::parse_lenient(&reordered)?, expected);
+
+        let pretty = format!(
+            "{{\n  \"digest\": {},\n  \"timestamp\": {},\n  \"url\": {},\n  \"content\": {}\n}}",
+            fields["digest"], fields["timestamp"], fields["url"], content
+        );
+
+        assert!(SnapshotLine::parse(&pretty).is_err());
+        assert_eq!(SnapshotLine::parse_lenient(&pretty)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid_line_offset() -> Result<(), Box<dyn std::error::Error>> {
+        let line = include_str!("../../../examples/wxj/inferred-url-01.json").trim();
+        let url_start = line.find("\"url\":\"").ok_or("missing URL")? + URL_KEY_LEN + 4;
+        let truncated = &line[..url_start + 10];
+
+        match SnapshotLine::parse(truncated) {
+            Err(Error::InvalidLine { offset, reason }) => {
+                assert_eq!(offset, url_start);
+                assert_eq!(reason, "unterminated URL");
+            }
+            other => panic!("Expected invalid line error, got {other:?}"),
+        }
+
+        let validation = SnapshotLine::validate_lines(
+            std::io::BufReader::new(format!("{line}\n{truncated}\n").as_bytes()).lines(),
+        )?;
+
+        assert_eq!(
+            validation.invalid_lines,
+            vec![InvalidLine {
+                line: 2,
+                offset: url_start,
+                reason: "unterminated URL"
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_multibyte_after_key() -> Result<(), Box<dyn std::error::Error>> {
+        let line = include_str!("../../../examples/wxj/inferred-url-01.json").trim();
+        let digest_end = DIGEST_KEY_LEN + 5 + DIGEST_LEN;
+        let url_key_end = line.find("\"url\"").ok_or("missing URL")? + URL_KEY_LEN + 1;
+
+        for (offset, reason) in [(digest_end, "invalid digest"), (url_key_end, "invalid URL")] {
+            let input = format!("{}\u{1f600}{}", &line[..offset], &line[offset..]);
+
+            match SnapshotLine::parse(&input) {
+                Err(Error::InvalidLine {
+                    offset: error_offset,
+                    reason: error_reason,
+                }) => {
+                    assert_eq!(error_offset, offset);
+                    assert_eq!(error_reason, reason);
+                }
+                other => panic!("Expected invalid line error, got {other:?}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_escaped_url() -> Result<(), Box<dyn std::error::Error>> {
+        let (_, mut snapshot_line) = test_line(1);
+        snapshot_line.url = Some("https://twitter.com/jack/status/1?q=\"a\\b\"\t".into());
+
+        let line = snapshot_line.to_checked_string()?;
+
+        assert!(line.contains("?q=\\\"a\\\\b\\\"\\t\","));
+        assert!(serde_json::from_str::<serde::de::IgnoredAny>(&line).is_ok());
+        assert_eq!(SnapshotLine::parse(&line)?, snapshot_line);
+        assert_eq!(SnapshotLine::parse_lenient(&line)?, snapshot_line);
+
+        snapshot_line.content = "{\"data\":".into();
+
+        assert!(snapshot_line.to_checked_string().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_millisecond_timestamp() -> Result<(), Box<dyn std::error::Error>> {
+        let (_, mut snapshot_line) = test_line(1);
+        snapshot_line.url = Some("https://twitter.com/jack/status/1".into());
+
+        for timestamp in ["20160508215503", "20160508215503123"] {
+            snapshot_line.timestamp = Some(timestamp.parse()?);
+
+            let line = snapshot_line.to_string();
+
+            assert!(line.contains(&format!("\"timestamp\":\"{timestamp}\",")));
+            assert_eq!(SnapshotLine::parse(&line)?, snapshot_line);
+            assert_eq!(SnapshotLine::parse_lenient(&line)?, snapshot_line);
+        }
+
+        for invalid in ["2016050821550", "201605082155031", "20160508215503x"] {
+            let line = snapshot_line
+                .to_string()
+                .replace("20160508215503123", invalid);
+
+            assert!(matches!(
+                SnapshotLine::parse(&line),
+                Err(Error::InvalidLine {
+                    reason: "invalid timestamp",
+                    ..
+                })
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_lines_parallel_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let lines = include_str!("../../../examples/wxj/lines-01.ndjson");
+        let content = "{\"data\":{\"id\":\"1\"}\r\r\n";
+        let digest = sha1_digest(content);
+
+        // Include an invalid line, a line with invalid content, and a repeated line.
+        let input = format!(
+            "{lines}\n{{\"digest\":\n{}\n{}\n",
+            SnapshotLine::new(digest, content),
+            lines.lines().next().unwrap_or_default()
+        );
+
+        let serial =
+            SnapshotLine::validate_lines(std::io::BufReader::new(input.as_bytes()).lines())?;
+        let parallel = SnapshotLine::validate_lines_parallel(
+            std::io::BufReader::new(input.as_bytes()).lines(),
+        )?;
+
+        assert_eq!(parallel, serial);
+        assert_eq!(parallel.invalid_lines.len(), 1);
+        assert!(parallel.invalid_content.is_empty());
+
+        let serial = SnapshotLine::validate_lines_with_content(
+            std::io::BufReader::new(input.as_bytes()).lines(),
+        )?;
+        let parallel = SnapshotLine::validate_lines_parallel_with_content(
+            std::io::BufReader::new(input.as_bytes()).lines(),
+        )?;
+
+        assert_eq!(parallel, serial);
+        assert_eq!(parallel.invalid_lines.len(), 1);
+        assert_eq!(parallel.invalid_content, vec![digest]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_url_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let lines = include_str!("../../../examples/wxj/lines-01.ndjson").split("\n");
+
+        for line in lines {
+            let snapshot_line = SnapshotLine::parse(line)?;
+
+            assert!(snapshot_line.canonical_url()?.is_some());
+        }
+
+        let line = include_str!("../../../examples/wxj/inferred-url-01.json").trim();
+        let snapshot_line = SnapshotLine::parse(line)?;
+
+        assert_eq!(snapshot_line.canonical_url()?, snapshot_line.url.clone());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tweet_ids_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let lines = include_str!("../../../examples/wxj/lines-01.ndjson")
+            .split("\n")
+            .chain(std::iter::once(
+                include_str!("../../../examples/wxj/inferred-url-01.json").trim(),
+            ));
+
+        for line in lines {
+            let snapshot_line = SnapshotLine::parse(line)?;
+            let snapshot = serde_json::from_str::<data::TweetSnapshot>(&snapshot_line.content)?;
+
+            assert_eq!(
+                snapshot_line.tweet_ids()?,
+                Some((snapshot.data.id, snapshot.data.author_id))
+            );
+            assert_eq!(snapshot_line.tweet_id()?, Some(snapshot.data.id));
+        }
+
+        let content = "{\"created_at\":\"Wed Oct 10 20:19:24 +0000 2018\",\"id\":1050118621198921728,\"id_str\":\"1050118621198921728\",\"user\":{\"id\":6253282,\"id_str\":\"6253282\"}}\r\r\n";
+        let digest = sha1_digest(content);
+        let snapshot_line = SnapshotLine::new(digest, content);
+
+        assert_eq!(
+            snapshot_line.tweet_ids()?,
+            Some((1050118621198921728, 6253282))
+        );
+        assert_eq!(snapshot_line.tweet_id()?, Some(1050118621198921728));
+
+        // The tweet ID is available without an author ID.
+        let (_, snapshot_line) = test_line(2);
+
+        assert_eq!(snapshot_line.tweet_ids()?, None);
+        assert_eq!(snapshot_line.tweet_id()?, Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn dedup_by_tweet_id() -> Result<(), Box<dyn std::error::Error>> {
+        let contents = [
+            (
+                "{\"data\":{\"id\":\"1\",\"author_id\":\"10\",\"text\":\"a\"}}\r\r\n",
+                Some("20200101000000"),
+            ),
+            (
+                "{\"data\":{\"id\":\"1\",\"author_id\":\"10\",\"text\":\"b\"}}\r\r\n",
+                Some("20210101000000"),
+            ),
+            (
+                "{\"data\":{\"id\":\"1\",\"author_id\":\"10\",\"text\":\"c\"}}\r\r\n",
+                None,
+            ),
+            ("{\"data\":{\"id\":\"2\",\"author_id\":\"10\"}}\r\r\n", None),
+            // Duplicate captures without author IDs.
+            (
+                "{\"data\":{\"id\":\"3\",\"text\":\"a\"}}\r\r\n",
+                Some("20200101000000"),
+            ),
+            ("{\"data\":{\"id\":\"3\",\"text\":\"b\"}}\r\r\n", None),
+            // Content in an unknown format is always retained.
+            ("{\"errors\":[]}\r\r\n", None),
+        ];
+
+        let mut lines = vec![];
+
+        for (content, timestamp) in contents {
+            let digest = sha1_digest(content);
+            let mut snapshot_line = SnapshotLine::new(digest, content).into_owned();
+            snapshot_line.timestamp = timestamp.map(|timestamp| timestamp.parse()).transpose()?;
+
+            lines.push(snapshot_line);
+        }
+
+        let deduped = SnapshotLine::dedup_by_tweet_id(lines.iter().cloned().map(Ok))?;
+
+        let mut expected = vec![
+            lines[1].clone(),
+            lines[3].clone(),
+            lines[4].clone(),
+            lines[6].clone(),
+        ];
+        expected.sort_by_key(|snapshot_line| snapshot_line.digest);
+
+        assert_eq!(deduped, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn url_tweet_id_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+        let line = include_str!("../../../examples/wxj/inferred-url-01.json").trim();
+        let snapshot_line = SnapshotLine::parse(line)?;
+
+        assert_eq!(snapshot_line.url_tweet_id(), Some(1919811194589786122));
+        assert_eq!(snapshot_line.url_tweet_id_mismatch()?, None);
+
+        // A mismatch is reported whether or not the content includes an author ID.
+        let content = "{\"data\":{\"id\":\"2\",\"author_id\":\"10\"}}\r\r\n";
+        let (_, author_less_line) = test_line(2);
+
+        for mut snapshot_line in [
+            SnapshotLine::new(sha1_digest(content), content),
+            author_less_line,
+        ] {
+            snapshot_line.url = Some("https://twitter.com/jack/status/20?lang=en".into());
+
+            assert_eq!(snapshot_line.url_tweet_id(), Some(20));
+            assert_eq!(snapshot_line.url_tweet_id_mismatch()?, Some((20, 2)));
+
+            snapshot_line.url = None;
+
+            assert_eq!(snapshot_line.url_tweet_id_mismatch()?, None);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn validate_all_examples() -> Result<(), Box<dyn std::error::Error>> {
         let lines = std::io::BufReader::new(std::io::Cursor::new(
@@ -455,6 +1565,165 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn digest_under() -> Result<(), Box<dyn std::error::Error>> {
+        let mut hasher = Sha1::default();
+        let content = "{\"data\":{\"id\":\"1\"}}";
+
+        let odd_content = format!("{content}\n");
+        let odd_digest = sha1_digest(&odd_content);
+        let odd_line = SnapshotLine::new(odd_digest, &odd_content);
+
+        let default_content = format!("{content}\r\r\n");
+        let default_digest = sha1_digest(&default_content);
+        let default_line = SnapshotLine::new(default_digest, &default_content);
+
+        let stripped_digest = sha1_digest(content);
+
+        assert_ne!(odd_digest, default_digest);
+
+        assert_eq!(
+            odd_line.digest_under(DigestPolicy::Raw, &mut hasher),
+            odd_digest
+        );
+        assert_eq!(
+            default_line.digest_under(DigestPolicy::Raw, &mut hasher),
+            default_digest
+        );
+
+        assert_eq!(
+            odd_line.digest_under(DigestPolicy::NormalizeTrailingWhitespace, &mut hasher),
+            default_digest
+        );
+        assert_eq!(
+            default_line.digest_under(DigestPolicy::NormalizeTrailingWhitespace, &mut hasher),
+            default_digest
+        );
+
+        assert_eq!(
+            odd_line.digest_under(DigestPolicy::StripAllTrailingWhitespace, &mut hasher),
+            stripped_digest
+        );
+        assert_eq!(
+            default_line.digest_under(DigestPolicy::StripAllTrailingWhitespace, &mut hasher),
+            stripped_digest
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_closing_whitespace() -> Result<(), Box<dyn std::error::Error>> {
+        for closing_whitespace in ["", "\n", "\r\n", "\r\r\n", "\n\r\r\n", "\r\n\n"] {
+            let content = format!("{{\"data\":{{\"id\":\"1\"}}}}{closing_whitespace}");
+            let digest = sha1_digest(&content);
+            let snapshot_line = SnapshotLine::new(digest, &content);
+
+            assert_eq!(snapshot_line.content, "{\"data\":{\"id\":\"1\"}}");
+            assert_eq!(
+                snapshot_line.closing_whitespace_bytes(),
+                closing_whitespace.as_bytes()
+            );
+            assert_eq!(snapshot_line.validate(&mut Default::default()), Ok(()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn repair_closing_whitespace() -> Result<(), Box<dyn std::error::Error>> {
+        let mut hasher = Sha1::default();
+        let content = "{\"data\":{\"id\":\"1\"}}\r\n";
+        let digest = sha1_digest(content);
+
+        let mut snapshot_line = SnapshotLine::new(digest, content);
+        snapshot_line.content = content.into();
+        snapshot_line.closing_whitespace = None;
+
+        assert!(snapshot_line.validate(&mut hasher).is_err());
+        assert_eq!(snapshot_line.repair_closing_whitespace(&mut hasher), Ok(()));
+        assert_eq!(snapshot_line.content, "{\"data\":{\"id\":\"1\"}}");
+        assert_eq!(snapshot_line.closing_whitespace, Some(vec!['\r', '\n']));
+
+        let repaired = snapshot_line.clone();
+
+        assert_eq!(snapshot_line.repair_closing_whitespace(&mut hasher), Ok(()));
+        assert_eq!(snapshot_line, repaired);
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_content_json() -> Result<(), Box<dyn std::error::Error>> {
+        let content = "{\"data\":{\"id\":\"1\"}\r\r\n";
+        let digest = sha1_digest(content);
+
+        let snapshot_line = SnapshotLine::new(digest, content);
+
+        assert_eq!(snapshot_line.validate(&mut Default::default()), Ok(()));
+        assert!(snapshot_line.validate_content_json().is_err());
+
+        let line = snapshot_line.to_string();
+
+        // Content is only checked when requested.
+        let validation =
+            SnapshotLine::validate_lines(std::io::BufReader::new(line.as_bytes()).lines())?;
+
+        assert_eq!(validation.valid_count, 1);
+        assert!(validation.is_successful());
+
+        let validation = SnapshotLine::validate_lines_with_content(
+            std::io::BufReader::new(line.as_bytes()).lines(),
+        )?;
+
+        assert_eq!(validation.valid_count, 0);
+        assert_eq!(validation.invalid_content, vec![digest]);
+        assert!(!validation.is_successful());
+
+        Ok(())
+    }
+
+    #[test]
+    fn explain() -> Result<(), Box<dyn std::error::Error>> {
+        let (good_digest, good_content) = snapshot_content(1);
+
+        let bad_content = "{\"data\":{\"id\":\"2\"}\n";
+        let bad_computed_digest = sha1_digest(bad_content);
+        let bad_digest = Sha1Digest::MAX;
+
+        let input = format!(
+            "{}\n{}\n",
+            SnapshotLine::new(good_digest, &good_content),
+            SnapshotLine::new(bad_digest, bad_content)
+        );
+
+        let lines = std::io::BufReader::new(std::io::Cursor::new(input.as_bytes())).lines();
+        let (line_number, explanation) =
+            SnapshotLineExplanation::find(lines, bad_digest)?.ok_or("Expected line")?;
+
+        assert_eq!(line_number, 2);
+        assert_eq!(explanation.stored_digest, bad_digest);
+        assert_eq!(explanation.computed_digest, bad_computed_digest);
+        assert_eq!(explanation.closing_whitespace, b"\n");
+        assert_eq!(explanation.content_len, bad_content.len() - 1);
+        assert!(explanation.content_json_error.is_some());
+        assert!(!explanation.is_valid());
+
+        let lines = std::io::BufReader::new(std::io::Cursor::new(input.as_bytes())).lines();
+        let (line_number, explanation) =
+            SnapshotLineExplanation::find(lines, good_digest)?.ok_or("Expected line")?;
+
+        assert_eq!(line_number, 1);
+        assert_eq!(explanation.closing_whitespace, DEFAULT_CLOSING_WHITESPACE);
+        assert!(explanation.is_valid());
+
+        let lines = std::io::BufReader::new(std::io::Cursor::new(input.as_bytes())).lines();
+
+        assert_eq!(SnapshotLineExplanation::find(lines, Sha1Digest::MIN)?, None);
+
+        Ok(())
+    }
+
     #[test]
     fn deserialize_examples() -> Result<(), Box<dyn std::error::Error>> {
         let lines = include_str!("../../../examples/wxj/lines-01.ndjson").split("\n");
@@ -467,6 +1736,23 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn to_line_string_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let lines = include_str!("../../../examples/wxj/lines-01.ndjson")
+            .split("\n")
+            .chain(std::iter::once(
+                include_str!("../../../examples/wxj/inferred-url-01.json").trim(),
+            ));
+
+        for line in lines {
+            let snapshot = serde_json::from_str::<Snapshot<serde_json::Value>>(line)?;
+
+            assert_eq!(snapshot.to_line_string()?, line);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn snapshot_line_snapshot_match() -> Result<(), Box<dyn std::error::Error>> {
         let lines = include_str!("../../../examples/wxj/lines-01.ndjson").split("\n");
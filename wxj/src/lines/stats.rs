@@ -0,0 +1,224 @@
+//! Summary statistics for snapshot line files.
+
+use super::{Error, SnapshotLine};
+use archivindex_wbm::digest::Sha1Digest;
+use std::collections::HashMap;
+
+/// Counts of snapshot lines by which optional fields they include.
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Serialize)]
+pub struct LineStats {
+    pub line_count: usize,
+    pub with_timestamp: usize,
+    pub without_timestamp: usize,
+    pub with_url: usize,
+    pub without_url: usize,
+    /// Lines whose closing whitespace is not the default (`\r\r\n`).
+    pub non_default_closing_whitespace: usize,
+    pub with_expected_digest: usize,
+    pub min_digest: Option<Sha1Digest>,
+    pub max_digest: Option<Sha1Digest>,
+}
+
+impl LineStats {
+    pub fn add(&mut self, snapshot_line: &SnapshotLine) {
+        self.line_count += 1;
+
+        if snapshot_line.timestamp.is_some() {
+            self.with_timestamp += 1;
+        } else {
+            self.without_timestamp += 1;
+        }
+
+        if snapshot_line.url.is_some() {
+            self.with_url += 1;
+        } else {
+            self.without_url += 1;
+        }
+
+        if snapshot_line.closing_whitespace_bytes() != super::DEFAULT_CLOSING_WHITESPACE {
+            self.non_default_closing_whitespace += 1;
+        }
+
+        if snapshot_line.expected_digest.is_some() {
+            self.with_expected_digest += 1;
+        }
+
+        self.update_digest_range(Some(snapshot_line.digest), Some(snapshot_line.digest));
+    }
+
+    /// Combine counts from another file.
+    pub fn merge(&mut self, other: &Self) {
+        self.line_count += other.line_count;
+        self.with_timestamp += other.with_timestamp;
+        self.without_timestamp += other.without_timestamp;
+        self.with_url += other.with_url;
+        self.without_url += other.without_url;
+        self.non_default_closing_whitespace += other.non_default_closing_whitespace;
+        self.with_expected_digest += other.with_expected_digest;
+
+        self.update_digest_range(other.min_digest, other.max_digest);
+    }
+
+    fn update_digest_range(
+        &mut self,
+        min_digest: Option<Sha1Digest>,
+        max_digest: Option<Sha1Digest>,
+    ) {
+        self.min_digest = match (self.min_digest, min_digest) {
+            (Some(current), Some(new)) => Some(current.min(new)),
+            (current, new) => current.or(new),
+        };
+
+        self.max_digest = match (self.max_digest, max_digest) {
+            (Some(current), Some(new)) => Some(current.max(new)),
+            (current, new) => current.or(new),
+        };
+    }
+}
+
+impl std::fmt::Display for LineStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Lines: {}", self.line_count)?;
+        writeln!(
+            f,
+            "Timestamp: {} with, {} without",
+            self.with_timestamp, self.without_timestamp
+        )?;
+        writeln!(
+            f,
+            "URL: {} with, {} without",
+            self.with_url, self.without_url
+        )?;
+        writeln!(
+            f,
+            "Non-default closing whitespace: {}",
+            self.non_default_closing_whitespace
+        )?;
+        writeln!(f, "Expected digest: {}", self.with_expected_digest)?;
+
+        match (self.min_digest, self.max_digest) {
+            (Some(min_digest), Some(max_digest)) => {
+                write!(f, "Digest range: {min_digest} to {max_digest}")
+            }
+            _ => write!(f, "Digest range: none"),
+        }
+    }
+}
+
+/// Tweet counts by author ID.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UserCounts {
+    counts: HashMap<u64, usize>,
+    /// The number of lines without an author ID.
+    pub unknown: usize,
+}
+
+impl UserCounts {
+    pub fn add(&mut self, snapshot_line: &SnapshotLine) -> Result<(), Error> {
+        match snapshot_line.tweet_ids()? {
+            Some((_, user_id)) => {
+                *self.counts.entry(user_id).or_default() += 1;
+            }
+            None => {
+                self.unknown += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, user_id: u64) -> usize {
+        self.counts.get(&user_id).copied().unwrap_or_default()
+    }
+
+    /// The users with the most tweets, in descending order by count (and then ascending order by ID).
+    pub fn top(&self, n: usize) -> Vec<(u64, usize)> {
+        let mut counts = self
+            .counts
+            .iter()
+            .map(|(user_id, count)| (*user_id, *count))
+            .collect::<Vec<_>>();
+
+        counts.sort_unstable_by(|(user_id_a, count_a), (user_id_b, count_b)| {
+            count_b.cmp(count_a).then(user_id_a.cmp(user_id_b))
+        });
+        counts.truncate(n);
+
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_stats_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let mut stats = LineStats::default();
+
+        for line in include_str!("../../../examples/wxj/lines-01.ndjson").split("\n") {
+            stats.add(&SnapshotLine::parse(line)?);
+        }
+
+        let mut inferred_url_stats = LineStats::default();
+        inferred_url_stats.add(&SnapshotLine::parse(
+            include_str!("../../../examples/wxj/inferred-url-01.json").trim(),
+        )?);
+
+        assert_eq!(
+            stats,
+            LineStats {
+                line_count: 2,
+                with_timestamp: 0,
+                without_timestamp: 2,
+                with_url: 0,
+                without_url: 2,
+                non_default_closing_whitespace: 1,
+                with_expected_digest: 0,
+                min_digest: Some("AAA6N44YBG3G3WVGZGNGPRPUMQU2MYQ6".parse()?),
+                max_digest: Some("AAEKAXL7SXHL3CBHWTAX3KIE56JLCYBB".parse()?),
+            }
+        );
+
+        stats.merge(&inferred_url_stats);
+
+        assert_eq!(stats.line_count, 3);
+        assert_eq!(stats.with_timestamp, 1);
+        assert_eq!(stats.with_url, 1);
+        assert_eq!(
+            stats.max_digest,
+            Some("AAPV3XJTMCF6MMDMTKKUSUVIWJM35HCW".parse()?)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn user_counts() -> Result<(), Box<dyn std::error::Error>> {
+        let mut contents = vec![];
+
+        for (tweet_id, user_id) in [(1, 10), (2, 30), (3, 10), (4, 20), (5, 30), (6, 10)] {
+            contents.push(format!(
+                "{{\"data\":{{\"id\":\"{tweet_id}\",\"author_id\":\"{user_id}\"}}}}\r\r\n"
+            ));
+        }
+
+        contents.push("{\"errors\":[]}\r\r\n".to_string());
+
+        let mut user_counts = UserCounts::default();
+
+        for content in &contents {
+            let digest = archivindex_wbm::testing::sha1_digest(content);
+
+            user_counts.add(&SnapshotLine::new(digest, content))?;
+        }
+
+        assert_eq!(user_counts.top(2), vec![(10, 3), (30, 2)]);
+        assert_eq!(user_counts.top(5), vec![(10, 3), (30, 2), (20, 1)]);
+        assert_eq!(user_counts.get(20), 1);
+        assert_eq!(user_counts.get(40), 0);
+        assert_eq!(user_counts.unknown, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! Detection of tweet snapshot content formats.
+
+use birdsite::model::wxj::TweetSnapshot;
+
+const DATA_CONTENT_PREFIX: &str = "{\"data\":";
+const FLAT_CONTENT_PREFIX: &str = "{\"created_at\":";
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TweetSnapshotKind {
+    /// Version 2 API format.
+    Data,
+    /// Version 1.1 API format.
+    Flat,
+}
+
+impl TweetSnapshotKind {
+    /// Classify content by its first field, without parsing it.
+    pub fn from_content(content: &str) -> Option<Self> {
+        if content.starts_with(DATA_CONTENT_PREFIX) {
+            Some(Self::Data)
+        } else if content.starts_with(FLAT_CONTENT_PREFIX) {
+            Some(Self::Flat)
+        } else {
+            None
+        }
+    }
+
+    pub fn parse(self, content: &str) -> Result<TweetSnapshot, super::Error> {
+        Ok(match self {
+            Self::Data => TweetSnapshot::Data(serde_json::from_str(content)?),
+            Self::Flat => TweetSnapshot::Flat(serde_json::from_str(content)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::SnapshotLine;
+
+    #[test]
+    fn classify_and_parse_examples() -> Result<(), Box<dyn std::error::Error>> {
+        let lines = include_str!("../../../examples/wxj/lines-01.ndjson").split("\n");
+
+        for line in lines {
+            let snapshot_line = SnapshotLine::parse(line)?;
+            let kind =
+                TweetSnapshotKind::from_content(&snapshot_line.content).ok_or("Expected kind")?;
+
+            assert_eq!(kind, TweetSnapshotKind::Data);
+            assert!(matches!(
+                kind.parse(&snapshot_line.content)?,
+                TweetSnapshot::Data(_)
+            ));
+        }
+
+        assert_eq!(
+            TweetSnapshotKind::from_content(
+                "{\"created_at\":\"Wed Oct 10 20:19:24 +0000 2018\",\"id\":1050118621198921728}"
+            ),
+            Some(TweetSnapshotKind::Flat)
+        );
+        assert_eq!(TweetSnapshotKind::from_content("{\"errors\":[]}"), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+//! Mappings from snapshot digests to Twitter URL paths.
+//!
+//! The input is a headerless CSV file with a digest and an optional Twitter URL in each row.
+
+use archivindex_wbm::digest::Sha1Digest;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+const TWITTER_URL_PREFIX: &str = "https://twitter.com";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[error("CSV error")]
+    Csv(#[from] csv::Error),
+    #[error("Unsorted input at digest {0}")]
+    Unsorted(Sha1Digest),
+    #[error("Duplicate digest {0}")]
+    Duplicate(Sha1Digest),
+}
+
+/// Look up URL paths by digest.
+///
+/// The outer option indicates whether the digest is present, and the inner option whether it has a URL path.
+pub trait UrlPathLookup {
+    fn lookup(&mut self, digest: Sha1Digest) -> Result<Option<Option<String>>, Error>;
+}
+
+impl UrlPathLookup for BTreeMap<Sha1Digest, Option<String>> {
+    fn lookup(&mut self, digest: Sha1Digest) -> Result<Option<Option<String>>, Error> {
+        Ok(self.get(&digest).cloned())
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct DigestUrl {
+    digest: Sha1Digest,
+    url: Option<String>,
+}
+
+impl DigestUrl {
+    fn into_url_path(self) -> Result<(Sha1Digest, Option<String>), csv::Error> {
+        let url_path = self
+            .url
+            .map(|url| {
+                url.strip_prefix(TWITTER_URL_PREFIX)
+                    .map(|path| path.to_string())
+                    .ok_or_else(|| <csv::Error as serde::ser::Error>::custom("Twitter URL"))
+            })
+            .map_or(Ok(None), |value| value.map(Some))?;
+
+        Ok((self.digest, url_path))
+    }
+}
+
+/// Read the entire mapping into memory.
+pub fn read_url_paths<P: AsRef<Path>>(
+    input: P,
+) -> Result<BTreeMap<Sha1Digest, Option<String>>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_path(input)?;
+
+    reader
+        .deserialize::<DigestUrl>()
+        .map(|row| row.and_then(DigestUrl::into_url_path).map_err(Error::from))
+        .collect()
+}
+
+/// A sparse index over a CSV file that is sorted by digest, with no duplicate digests.
+///
+/// Only every `interval`-th digest is kept in memory, and lookups seek to the closest indexed row and scan forward,
+/// so memory use is bounded at the cost of a seek and parsing up to `interval` rows per lookup. For large numbers of
+/// lookups in random order (e.g. one per CDX row) this is roughly `interval` times the cost of reading the whole
+/// file, so the interval should be small, or `read_url_paths` should be used if the mapping fits in memory.
+///
+/// Duplicate digests are rejected when the index is opened, since the in-memory mapping would keep the last value
+/// for a digest while a scan would find the first.
+pub struct UrlPathIndex {
+    reader: csv::Reader<File>,
+    index: Vec<(Sha1Digest, csv::Position)>,
+    interval: usize,
+    len: usize,
+}
+
+impl UrlPathIndex {
+    pub fn open<P: AsRef<Path>>(input: P, interval: usize) -> Result<Self, Error> {
+        let interval = interval.max(1);
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path(input)?;
+
+        let mut index = vec![];
+        let mut len = 0;
+        let mut last_digest = None;
+        let mut record = csv::StringRecord::new();
+
+        loop {
+            let position = reader.position().clone();
+
+            if !reader.read_record(&mut record)? {
+                break;
+            }
+
+            let (digest, _) = record.deserialize::<DigestUrl>(None)?.into_url_path()?;
+
+            if let Some(last_digest) = last_digest {
+                if digest < last_digest {
+                    return Err(Error::Unsorted(digest));
+                } else if digest == last_digest {
+                    return Err(Error::Duplicate(digest));
+                }
+            }
+
+            if len % interval == 0 {
+                index.push((digest, position));
+            }
+
+            last_digest = Some(digest);
+            len += 1;
+        }
+
+        Ok(Self {
+            reader,
+            index,
+            interval,
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl UrlPathLookup for UrlPathIndex {
+    fn lookup(&mut self, digest: Sha1Digest) -> Result<Option<Option<String>>, Error> {
+        let index_position = self
+            .index
+            .partition_point(|(indexed_digest, _)| *indexed_digest <= digest);
+
+        if index_position == 0 {
+            Ok(None)
+        } else {
+            self.reader.seek(self.index[index_position - 1].1.clone())?;
+
+            let mut record = csv::StringRecord::new();
+
+            for _ in 0..self.interval {
+                if !self.reader.read_record(&mut record)? {
+                    break;
+                }
+
+                let (next_digest, url_path) =
+                    record.deserialize::<DigestUrl>(None)?.into_url_path()?;
+
+                if next_digest == digest {
+                    return Ok(Some(url_path));
+                } else if next_digest > digest {
+                    break;
+                }
+            }
+
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_csv<P: AsRef<Path>>(path: P, digests: &[Sha1Digest]) -> Result<(), std::io::Error> {
+        let mut output = File::create(path)?;
+
+        for (i, digest) in digests.iter().enumerate() {
+            if i % 3 == 0 {
+                writeln!(output, "{digest},")?;
+            } else {
+                writeln!(output, "{digest},https://twitter.com/jack/status/{i}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn index_matches_in_memory() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("urls.csv");
+
+        let digests = (0..20).map(|i| Sha1Digest([i * 2; 20])).collect::<Vec<_>>();
+        write_csv(&path, &digests)?;
+
+        let mut in_memory = read_url_paths(&path)?;
+        let mut index = UrlPathIndex::open(&path, 3)?;
+
+        assert_eq!(index.len(), in_memory.len());
+
+        for i in 0..=u8::MAX {
+            let digest = Sha1Digest([i; 20]);
+
+            assert_eq!(index.lookup(digest)?, in_memory.lookup(digest)?);
+        }
+
+        assert_eq!(
+            index.lookup(digests[1])?,
+            Some(Some("/jack/status/1".to_string()))
+        );
+        assert_eq!(index.lookup(digests[3])?, Some(None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn index_unsorted() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("urls.csv");
+
+        let digests = [Sha1Digest::MIN, Sha1Digest::MAX, Sha1Digest([1; 20])];
+        write_csv(&path, &digests)?;
+
+        assert!(matches!(
+            UrlPathIndex::open(&path, 3),
+            Err(Error::Unsorted(digest)) if digest == Sha1Digest([1; 20])
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn index_duplicate() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("urls.csv");
+
+        let digests = [Sha1Digest::MIN, Sha1Digest([1; 20]), Sha1Digest([1; 20])];
+        write_csv(&path, &digests)?;
+
+        let error = UrlPathIndex::open(&path, 3).err().ok_or("Expected error")?;
+
+        assert!(matches!(error, Error::Duplicate(digest) if digest == Sha1Digest([1; 20])));
+        assert_eq!(
+            error.to_string(),
+            format!("Duplicate digest {}", Sha1Digest([1; 20]))
+        );
+
+        Ok(())
+    }
+}